@@ -0,0 +1,76 @@
+use rand::Rng;
+use rand_distr::{Distribution, Gamma, Poisson};
+use serde::{Deserialize, Serialize};
+
+/// Distribution of the number of users arriving in a block, given the value of the demand profile
+/// for that block as the arrival rate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum Arrivals {
+    /// Exactly as many users as given by the demand profile
+    #[default]
+    Fixed,
+    /// Poisson distributed number of users, with the demand profile as the mean
+    Poisson,
+    /// Negative binomial distributed number of users, with the demand profile as the mean and a
+    /// variance of `mean + mean^2 / dispersion`. Lower dispersions give burstier arrivals, and the
+    /// distribution tends to Poisson as the dispersion grows.
+    NegativeBinomial { dispersion: f64 },
+}
+
+impl Arrivals {
+    /// Draws the number of users arriving in a block
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R, rate: u64) -> u64 {
+        if rate == 0 {
+            return 0;
+        }
+        let rate = rate as f64;
+
+        match *self {
+            Arrivals::Fixed => rate as u64,
+            Arrivals::Poisson => Poisson::new(rate).unwrap().sample(rng),
+            Arrivals::NegativeBinomial { dispersion } => {
+                // Poisson distribution whose rate is Gamma distributed
+                let rate: f64 = Gamma::new(dispersion, rate / dispersion)
+                    .expect("Dispersion of the arrivals must be positive")
+                    .sample(rng);
+                if rate > 0. {
+                    Poisson::new(rate).unwrap().sample(rng)
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arrivals;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use stats::{mean, variance};
+
+    #[test]
+    fn test_arrival_moments() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let rate = 1000;
+
+        let sample = |arrivals: Arrivals, rng: &mut StdRng| -> Vec<f64> {
+            (0..20_000)
+                .map(|_| arrivals.sample(rng, rate) as f64)
+                .collect()
+        };
+
+        assert_eq!(Arrivals::Fixed.sample(&mut rng, rate), rate);
+
+        let poisson = sample(Arrivals::Poisson, &mut rng);
+        assert!((mean(poisson.iter().copied()) - 1000.).abs() < 5.);
+        assert!((variance(poisson.iter().copied()) / 1000. - 1.).abs() < 0.1);
+
+        let negative_binomial = sample(Arrivals::NegativeBinomial { dispersion: 10. }, &mut rng);
+        // Variance of 1000 + 1000^2 / 10
+        assert!((mean(negative_binomial.iter().copied()) - 1000.).abs() < 10.);
+        assert!((variance(negative_binomial.iter().copied()) / 101_000. - 1.).abs() < 0.1);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::transaction::Transaction;
+
+/// User priced out of the market, waiting for the price to drop to their willingness to pay
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaitingUser {
+    /// Index of the class of users the user belongs to
+    pub class: usize,
+    /// Willingness to pay per gas, in fiat
+    pub wtp: u64,
+    pub gas_used: u64,
+    /// Last height at which the user retries
+    pub deadline: u64,
+}
+
+/// Latent demand of the users who were priced out, and wait up to their patience for the price to
+/// drop before resubmitting
+#[derive(Default)]
+pub struct LatentBacklog {
+    users: Vec<WaitingUser>,
+}
+
+impl LatentBacklog {
+    pub fn new() -> LatentBacklog {
+        LatentBacklog::default()
+    }
+
+    /// Number of users waiting
+    pub fn size(&self) -> u64 {
+        self.users.len() as u64
+    }
+
+    pub fn users(&self) -> &[WaitingUser] {
+        &self.users
+    }
+
+    pub fn add(&mut self, user: WaitingUser) {
+        self.users.push(user);
+    }
+
+    /// Removes and returns the users who gave up waiting before the block at `height`
+    pub fn remove_abandoned(&mut self, height: u64) -> Vec<WaitingUser> {
+        let (abandoned, waiting) = self.users.iter().partition(|user| user.deadline < height);
+        self.users = waiting;
+        abandoned
+    }
+
+    /// Retries every waiting user with `price_tx`, which returns the transaction of the user or
+    /// `None` if they are still priced out. Returns the transactions sent, in the order the users
+    /// were priced out, and removes their senders from the backlog.
+    pub fn retry<F>(&mut self, mut price_tx: F) -> Vec<Transaction>
+    where
+        F: FnMut(&WaitingUser) -> Option<Transaction>,
+    {
+        let mut txs = Vec::new();
+        self.users.retain(|user| match price_tx(user) {
+            Some(tx) => {
+                txs.push(tx);
+                false
+            }
+            None => true,
+        });
+        txs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LatentBacklog, WaitingUser};
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_retry() {
+        let mut backlog = LatentBacklog::new();
+        for (wtp, deadline) in [(100, 5), (200, 3), (300, 10)].iter() {
+            backlog.add(WaitingUser {
+                class: 0,
+                wtp: *wtp,
+                gas_used: 21_000,
+                deadline: *deadline,
+            });
+        }
+
+        // Price of 250 until block 3, then of 150
+        let price_tx = |price: u64| {
+            move |user: &WaitingUser| {
+                Some(Transaction::new(user.gas_used, price)).filter(|_| user.wtp >= price)
+            }
+        };
+
+        let txs = backlog.retry(price_tx(250));
+        assert_eq!(txs.len(), 1);
+        assert_eq!(backlog.size(), 2);
+
+        let abandoned = backlog.remove_abandoned(4);
+        assert_eq!(abandoned.len(), 1);
+        assert_eq!(abandoned[0].wtp, 200);
+
+        assert!(backlog.retry(price_tx(150)).is_empty());
+        assert_eq!(backlog.users()[0].wtp, 100);
+    }
+}
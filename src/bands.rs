@@ -0,0 +1,111 @@
+use stats::mean;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::helper::quantile;
+use crate::summary::RunStats;
+
+/// Quantiles written for each per-block quantity, along with the mean
+const BAND_QUANTILES: [(&str, f64); 5] = [
+    ("p5", 0.05),
+    ("p25", 0.25),
+    ("median", 0.5),
+    ("p75", 0.75),
+    ("p95", 0.95),
+];
+
+/// Names of the per-block quantities, in the order of `BlockSeries::quantities`
+const QUANTITIES: [&str; 3] = ["gas_price", "fullness", "txpool_size"];
+
+/// Per-block quantities of a single run which are aggregated across replicas
+pub struct BlockSeries {
+    gas_price: Vec<f64>,
+    fullness: Vec<f64>,
+    txpool_size: Vec<f64>,
+}
+
+impl BlockSeries {
+    pub fn new(stats: &RunStats) -> BlockSeries {
+        BlockSeries {
+            gas_price: stats.gas_prices().to_vec(),
+            fullness: stats.fullness().to_vec(),
+            txpool_size: stats.txpool_sizes().to_vec(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.fullness.len()
+    }
+
+    fn quantities(&self) -> [&[f64]; 3] {
+        [&self.gas_price, &self.fullness, &self.txpool_size]
+    }
+}
+
+/// Mean and quantiles of a set of values, in the order of `BAND_QUANTILES`
+fn band(values: &mut [f64]) -> Vec<f64> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut band = vec![mean(values.iter().copied())];
+    band.extend(
+        BAND_QUANTILES
+            .iter()
+            .map(|&(_, q)| quantile(values, q).unwrap()),
+    );
+    band
+}
+
+/// Writes the mean and percentile bands of the per-block quantities across replicas. If some
+/// replicas stopped early, later blocks are aggregated over the remaining replicas, whose number is
+/// written to the `n_replicas` column.
+pub fn write_bands(path: &Path, series: &[BlockSeries], block_time: u64) {
+    let file = File::create(path).expect("Could not create the bands file");
+    let mut file = BufWriter::new(file);
+
+    let mut header = vec![
+        "height".to_string(),
+        "time".to_string(),
+        "n_replicas".to_string(),
+    ];
+    for name in QUANTITIES.iter() {
+        header.push(format!("{}_mean", name));
+        header.extend(
+            BAND_QUANTILES
+                .iter()
+                .map(|(suffix, _)| format!("{}_{}", name, suffix)),
+        );
+    }
+    file.write_all(format!("{}\n", header.join(",")).as_bytes())
+        .unwrap();
+
+    let n_blocks = series.iter().map(BlockSeries::len).max().unwrap_or(0);
+
+    for height in 0..n_blocks {
+        let replicas: Vec<&BlockSeries> = series.iter().filter(|s| s.len() > height).collect();
+
+        let mut row = vec![
+            height.to_string(),
+            (height as u64 * block_time).to_string(),
+            replicas.len().to_string(),
+        ];
+        for i in 0..QUANTITIES.len() {
+            let mut values: Vec<f64> = replicas.iter().map(|s| s.quantities()[i][height]).collect();
+            row.extend(band(&mut values).iter().map(f64::to_string));
+        }
+        file.write_all(format!("{}\n", row.join(",")).as_bytes())
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::band;
+
+    #[test]
+    fn test_band() {
+        let mut values = vec![4., 0., 2., 1., 3.];
+        assert_eq!(band(&mut values), vec![2., 0.2, 1., 2., 3., 3.8]);
+    }
+}
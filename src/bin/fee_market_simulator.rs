@@ -1,109 +1,94 @@
-use clap::{App, Arg};
-use config;
-use csv;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::fs::File;
-use std::path::PathBuf;
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::path::Path;
+use std::process;
 
-use fee_market_simulator::demand::DemandCurve;
-use fee_market_simulator::helper::LinearInterpolator;
-use fee_market_simulator::FeeMarketSimulator;
-
-fn read_demand_profile(path: &str) -> Vec<u64> {
-    let file = File::open(path).expect("Couldn't open input CSV file");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(file);
-
-    reader.records()
-        .map(|record| {
-            let record = record.unwrap();
-            record[0].parse::<u64>().unwrap()
-        })
-        .collect()
-}
+use fee_market_simulator::sweep::Sweep;
+use fee_market_simulator::{FeeMarketSimulator, SimulatorConfig};
 
 fn main() {
+    let config_arg = Arg::with_name("config")
+        .short("c")
+        .long("config")
+        .value_name("FILE")
+        .help("Path to the simulator config file")
+        .takes_value(true)
+        .required(true);
+
     let matches = App::new("Fee Market Simulator")
         .version("0.1")
         .author("Onur Solmaz <onursolmaz@gmail.com>")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(config_arg.clone())
         .arg(
-            Arg::with_name("config")
-                .short("c")
-                .long("config")
-                .value_name("FILE")
-                .help("Path to the simulator config file")
+            Arg::with_name("replicas")
+                .short("r")
+                .long("replicas")
+                .value_name("N")
+                .help("Number of replicas to run, overrides the config file")
                 .takes_value(true),
         )
+        .subcommand(
+            SubCommand::with_name("sweep")
+                .about("Runs the simulation for every combination of the swept parameters")
+                .arg(config_arg.help("Path to the base simulator config file"))
+                .arg(
+                    Arg::with_name("spec")
+                        .short("s")
+                        .long("spec")
+                        .value_name("FILE")
+                        .help("Path to the sweep spec file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .value_name("N")
+                        .help("Number of runs in parallel, defaults to the number of CPUs")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
-    let config_path = matches.value_of("config").unwrap();
-
-    let mut settings_ = config::Config::default();
-    settings_
-        .merge(config::File::with_name(config_path))
-        .unwrap();
-
-    let mut settings = settings_.try_into::<HashMap<String, String>>().unwrap();
-
-    let mut root_dir = PathBuf::from(&config_path);
-    root_dir = root_dir.parent().unwrap().to_path_buf();
-
-    let mut output_dir = root_dir.clone();
-    output_dir.push(&settings["output_dir"]);
-
-    let mut demand_curve_path = root_dir.clone();
-    demand_curve_path.push(&settings["demand_curve_path"]);
-
-    let mut demand_profile_path = root_dir.clone();
-    demand_profile_path.push(&settings["demand_profile_path"]);
-
-    let dc = DemandCurve::from_csv(
-        &demand_curve_path.to_str().unwrap(),
-        settings["interp_resolution"].parse().unwrap(),
-    );
-
-    let token_price: Option<LinearInterpolator> =
-        match settings.entry("token_price_path".to_string()) {
-            Entry::Occupied(o) => {
-                let mut token_price_path = root_dir.clone();
-                token_price_path.push(o.get());
-                let file = File::open(token_price_path)
-                    .expect(&format!("Couldn't open input CSV file {}", o.get()));
-
-                let mut reader = csv::ReaderBuilder::new()
-                    .has_headers(true)
-                    .from_reader(file);
-
-                let mut time: Vec<f64> = Vec::new();
-                let mut price: Vec<f64> = Vec::new();
+    if let Some(matches) = matches.subcommand_matches("sweep") {
+        if let Some(jobs) = matches.value_of("jobs") {
+            let jobs: usize = jobs.parse().unwrap_or_else(|_| {
+                eprintln!("Error: invalid number of jobs `{}`", jobs);
+                process::exit(1);
+            });
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build_global()
+                .unwrap();
+        }
+
+        let sweep = Sweep::from_files(
+            Path::new(matches.value_of("config").unwrap()),
+            Path::new(matches.value_of("spec").unwrap()),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        });
 
-                for record in reader.records() {
-                    let record = record.unwrap();
-                    time.push(record[0].parse().unwrap());
-                    price.push(record[1].parse().unwrap());
-                }
+        sweep.run();
+        return;
+    }
 
-                Some(LinearInterpolator::new(&time, &price))
-            }
-            Entry::Vacant(_v) => None,
-        };
+    let config_path = matches.value_of("config").unwrap();
 
-    let mut sim = FeeMarketSimulator::new_autoprice_simulator(
-        dc,
-        token_price,
-        settings["initial_price"].parse().unwrap(),
-        settings["block_gas_limit"].parse().unwrap(),
-        settings["tx_gas_used"].parse().unwrap(),
-        settings["txpool_size"].parse().unwrap(),
-        settings["block_time"].parse().unwrap(),
-        settings["control_range"].parse().unwrap(),
-        settings["target_fullness"].parse().unwrap(),
-        settings["price_adjustment_rate"].parse().unwrap(),
-    );
+    let mut config = SimulatorConfig::from_file(Path::new(config_path)).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
 
-    let demand_profile = read_demand_profile(&demand_profile_path.to_str().unwrap());
+    if let Some(replicas) = matches.value_of("replicas") {
+        config.replicas = replicas.parse().unwrap_or_else(|_| {
+            eprintln!("Error: invalid number of replicas `{}`", replicas);
+            process::exit(1);
+        });
+    }
 
-    sim.run(demand_profile, output_dir);
+    FeeMarketSimulator::run_config(&config);
 }
@@ -3,46 +3,155 @@ use stats::{mean, median};
 
 pub struct Block {
     txs: Vec<Transaction>,
+    gas_used: u64,
     gas_limit: u64,
+    base_fee: u64,
 }
 
 impl Block {
     pub fn new(gas_limit: u64) -> Block {
+        Block::with_base_fee(gas_limit, 0)
+    }
+
+    pub fn with_base_fee(gas_limit: u64, base_fee: u64) -> Block {
         Block {
             txs: Vec::new(),
-            gas_limit: gas_limit,
+            gas_used: 0,
+            gas_limit,
+            base_fee,
         }
     }
 
     pub fn add_txs(&mut self, txs: Vec<Transaction>) {
-        self.txs.extend(txs.iter().cloned())
+        self.gas_used += txs.iter().map(Transaction::gas_used).sum::<u64>();
+        self.txs.extend(txs)
+    }
+
+    /// Drops the transactions of the block, keeping its gas used and base fee. Quantities derived
+    /// from the transactions, such as prices and tips, are no longer available afterwards.
+    pub fn drop_txs(&mut self) {
+        self.txs = Vec::new();
     }
 
     pub fn gas_used(&self) -> u64 {
-        self.txs.iter().map(Transaction::gas_used).sum()
+        self.gas_used
     }
 
     pub fn fullness(&self) -> f64 {
         self.gas_used() as f64 / self.gas_limit as f64
     }
 
-    pub fn median_price(&self) -> u64 {
-        median(self.txs.iter().map(Transaction::gas_price)).unwrap() as u64
+    pub fn gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    pub fn base_fee(&self) -> u64 {
+        self.base_fee
+    }
+
+    /// Total amount of base fee burnt by the transactions in the block
+    pub fn burnt(&self) -> u64 {
+        self.base_fee * self.gas_used()
+    }
+
+    /// Total amount of fees received by the block producer
+    pub fn tips(&self) -> u64 {
+        self.txs
+            .iter()
+            .filter_map(|tx| {
+                tx.price_paid()
+                    .map(|price| tx.gas_used() * price.saturating_sub(self.base_fee))
+            })
+            .sum()
+    }
+
+    /// Total amount paid on top of the lowest gas price paid in the block
+    pub fn overpayment(&self) -> u64 {
+        match self.min_price() {
+            Some(min_price) => self
+                .txs
+                .iter()
+                .filter_map(|tx| {
+                    tx.price_paid()
+                        .map(|price| tx.gas_used() * (price - min_price))
+                })
+                .sum(),
+            None => 0,
+        }
+    }
+
+    /// Total difference between what the senders were willing to pay and what they paid
+    pub fn surplus(&self) -> f64 {
+        self.txs.iter().filter_map(Transaction::surplus).sum()
+    }
+
+    pub fn txs(&self) -> &[Transaction] {
+        &self.txs
+    }
+
+    fn paid_prices(&self) -> impl Iterator<Item = u64> + '_ {
+        self.txs.iter().filter_map(Transaction::price_paid)
+    }
+
+    pub fn median_price(&self) -> Option<u64> {
+        median(self.paid_prices()).map(|x| x as u64)
+    }
+
+    /// Mean price paid in the block, if any transaction was charged
+    pub fn mean_price(&self) -> Option<u64> {
+        if self.paid_prices().next().is_none() {
+            None
+        } else {
+            Some(mean(self.paid_prices()) as u64)
+        }
+    }
+
+    pub fn min_price(&self) -> Option<u64> {
+        self.paid_prices().min()
+    }
+
+    pub fn max_price(&self) -> Option<u64> {
+        self.paid_prices().max()
+    }
+
+    fn tx_gas_used(&self) -> impl Iterator<Item = u64> + '_ {
+        self.txs.iter().map(Transaction::gas_used)
     }
 
-    pub fn mean_price(&self) -> u64 {
-        mean(self.txs.iter().map(Transaction::gas_price)) as u64
+    pub fn min_tx_gas_used(&self) -> Option<u64> {
+        self.tx_gas_used().min()
     }
 
-    pub fn min_price(&self) -> u64 {
-        self.txs.iter().map(Transaction::gas_price).min().unwrap()
+    pub fn median_tx_gas_used(&self) -> Option<u64> {
+        median(self.tx_gas_used()).map(|x| x as u64)
     }
 
-    pub fn max_price(&self) -> u64 {
-        self.txs.iter().map(Transaction::gas_price).max().unwrap()
+    pub fn max_tx_gas_used(&self) -> Option<u64> {
+        self.tx_gas_used().max()
     }
 
     pub fn tx_count(&self) -> u64 {
         self.txs.len() as u64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Block;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_mean_price() {
+        let mut block = Block::new(100);
+        assert_eq!(block.mean_price(), None);
+
+        // Transactions which were not charged have no paid price to average
+        block.add_txs(vec![Transaction::new(10, 5)]);
+        assert_eq!(block.mean_price(), None);
+
+        let mut tx = Transaction::new(10, 5);
+        tx.include(0, 7);
+        block.add_txs(vec![tx]);
+        assert_eq!(block.mean_price(), Some(7));
+    }
+}
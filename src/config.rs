@@ -0,0 +1,1125 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::arrivals::Arrivals;
+use crate::demand::{
+    read_demand_profile, DemandCurve, DemandShape, DemandTransition, KeyframedDemandCurve,
+};
+use crate::demand_profile::DemandProfile;
+use crate::gas::GasUsage;
+use crate::helper::LinearInterpolator;
+use crate::mechanism::{AutoPrice, Eip1559, FeeMechanism, FiatAutoPrice, FirstPrice, Pid};
+use crate::oracle::PriceOracle;
+use crate::packing::PackingStrategy;
+use crate::token_price::{OutOfRange, TimeAlignment, TokenPriceGenerator, TokenPriceModel};
+use crate::transaction::{EvictionPolicy, Ttl};
+use crate::tx_log::TxLogFormat;
+use crate::user_class::UserClass;
+
+/// Configuration of a simulation, as read from a TOML config file. Relative paths are resolved
+/// against the directory of the config file when loaded with `from_file`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SimulatorConfig {
+    /// Directory the outputs are written to
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
+    /// Distribution of the number of users in each block, around the demand profile
+    #[serde(default)]
+    pub arrivals: Arrivals,
+    /// CSV file containing the token price in fiat versus time
+    #[serde(default)]
+    pub token_price_path: Option<PathBuf>,
+    /// Synthetic token price path, as an alternative to `token_price_path`
+    #[serde(default)]
+    pub token_price: Option<TokenPriceConfig>,
+    /// Timestamp of the token price data at which the run starts, the first one if not given
+    #[serde(default)]
+    pub token_price_start: Option<f64>,
+    /// Time after the first timestamp of the token price data at which the run starts [second],
+    /// as an alternative to `token_price_start`
+    #[serde(default)]
+    pub token_price_offset: Option<f64>,
+    /// Seconds of token price data covered by every second of the simulation
+    #[serde(default = "default_token_price_time_scale")]
+    pub token_price_time_scale: f64,
+    /// Behavior once the run goes past the end of the token price data
+    #[serde(default)]
+    pub token_price_out_of_range: OutOfRange,
+    /// On-chain oracle through which the fee mechanism sees the token price
+    #[serde(default)]
+    pub oracle: Option<OracleConfig>,
+    /// Size of the sample set derived from the demand curve
+    #[serde(default = "default_interp_resolution")]
+    pub interp_resolution: u64,
+    /// Maximum gas that can be used by transactions in a given block
+    pub block_gas_limit: u64,
+    /// Max. number of transactions in the transaction pool
+    #[serde(default = "default_txpool_size")]
+    pub txpool_size: usize,
+    /// Transactions dropped when the transaction pool is full
+    #[serde(default)]
+    pub txpool_eviction_policy: EvictionPolicy,
+    /// Strategy by which the block producer selects transactions from the transaction pool,
+    /// defaulting to the one of the mechanism
+    #[serde(default)]
+    pub block_packing: Option<PackingStrategy>,
+    /// Format of the per-transaction output log, if it should be written
+    #[serde(default)]
+    pub tx_log: Option<TxLogFormat>,
+    /// Time between 2 blocks [second]
+    pub block_time: u64,
+    /// Seed of the random number generator, a random seed is used if not given
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of replicas of the simulation, each run with a seed derived from `seed`
+    #[serde(default = "default_replicas")]
+    pub replicas: u64,
+    /// Single class of users, described at the top level of the config
+    #[serde(flatten)]
+    pub users: UserClassConfig,
+    /// Several classes of users, replacing the single class described at the top level
+    #[serde(default)]
+    pub user_classes: Option<Vec<UserClassConfig>>,
+    #[serde(flatten)]
+    pub mechanism: MechanismConfig,
+}
+
+/// Users sharing a demand curve, a demand profile, a distribution of gas usage and a
+/// time-to-live, such as retail transfers, DeFi traders or bots
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UserClassConfig {
+    /// Name of the class in the outputs
+    #[serde(default = "default_label")]
+    pub label: String,
+    /// CSV file containing the demand curve, i.e. price vs quantity
+    #[serde(default)]
+    pub demand_curve_path: Option<PathBuf>,
+    /// Parametric demand curve, as an alternative to `demand_curve_path`
+    #[serde(default)]
+    pub demand_curve: Option<DemandCurveConfig>,
+    /// Demand curves applying from given times, as an alternative to `demand_curve_path`
+    #[serde(default)]
+    pub demand_curve_keyframes: Option<Vec<DemandCurveKeyframe>>,
+    /// How the demand curve changes between keyframes
+    #[serde(default)]
+    pub demand_curve_transition: DemandTransition,
+    /// CSV file containing the number of users in each block
+    #[serde(default)]
+    pub demand_profile_path: Option<PathBuf>,
+    /// Generated number of users in each block, as an alternative to `demand_profile_path`
+    #[serde(default)]
+    pub demand_profile: Option<DemandProfile>,
+    /// Gas used by a single transaction
+    #[serde(default)]
+    pub tx_gas_used: Option<u64>,
+    /// Distribution of the gas used by transactions, as an alternative to `tx_gas_used`
+    #[serde(default)]
+    pub tx_gas: Option<GasConfig>,
+    /// Time-to-live of transactions, after which they are dropped from the transaction pool
+    #[serde(default)]
+    pub tx_ttl: Option<u64>,
+    #[serde(default)]
+    pub tx_ttl_unit: TtlUnit,
+    /// Number of blocks priced-out users wait for the price to drop to their willingness to pay
+    /// before giving up. Priced-out users leave right away if not given.
+    #[serde(default)]
+    pub patience: Option<u64>,
+}
+
+/// Fee mechanism and its parameters, selected with the `mechanism` key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "mechanism", rename_all = "snake_case")]
+pub enum MechanismConfig {
+    #[serde(rename = "autoprice")]
+    AutoPrice {
+        initial_price: u64,
+        control_range: u64,
+        target_fullness: f64,
+        price_adjustment_rate: f64,
+    },
+    /// AutoPrice adjusting a gas price in fiat, converted to tokens at the token price every block
+    #[serde(rename = "fiat_autoprice")]
+    FiatAutoPrice {
+        initial_fiat_price: f64,
+        control_range: u64,
+        target_fullness: f64,
+        price_adjustment_rate: f64,
+    },
+    /// Gas price set by a PID controller on the fullness error every `control_range` blocks
+    Pid {
+        initial_price: u64,
+        control_range: u64,
+        target_fullness: f64,
+        proportional_gain: f64,
+        #[serde(default)]
+        integral_gain: f64,
+        #[serde(default)]
+        derivative_gain: f64,
+        #[serde(default)]
+        integral_limit: Option<f64>,
+    },
+    Eip1559 {
+        initial_price: u64,
+        #[serde(default = "default_elasticity_multiplier")]
+        elasticity_multiplier: u64,
+        #[serde(default = "default_base_fee_max_change_denominator")]
+        base_fee_max_change_denominator: u64,
+        priority_fee_ratio: f64,
+    },
+    FirstPrice {
+        min_gas_price: u64,
+        #[serde(default = "default_bid_ratio")]
+        bid_ratio: f64,
+    },
+}
+
+/// Parametric demand curve, truncated to prices between `min_price` and `max_price`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DemandCurveConfig {
+    pub min_price: u64,
+    pub max_price: u64,
+    #[serde(flatten)]
+    pub shape: DemandShape,
+}
+
+impl DemandCurveConfig {
+    /// Checks the parameters of the curve, without building it
+    pub fn validate(&self, interp_resolution: u64) -> Result<(), String> {
+        DemandCurve::validate_parametric(
+            &self.shape,
+            self.min_price,
+            self.max_price,
+            interp_resolution,
+        )
+    }
+
+    pub fn build(&self, interp_resolution: u64) -> Result<DemandCurve, String> {
+        DemandCurve::parametric(
+            &self.shape,
+            self.min_price,
+            self.max_price,
+            interp_resolution,
+        )
+    }
+}
+
+/// Demand curve applying from a given time, either read from a CSV file or parametric
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DemandCurveKeyframe {
+    /// Time from which the curve applies [second]
+    pub time: u64,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub curve: Option<DemandCurveConfig>,
+}
+
+impl DemandCurveKeyframe {
+    pub fn build(&self, interp_resolution: u64) -> Result<DemandCurve, String> {
+        match (&self.path, &self.curve) {
+            (Some(path), None) => DemandCurve::from_csv(path, interp_resolution),
+            (None, Some(curve)) => curve.build(interp_resolution),
+            _ => Err("exactly one of `path` and `curve` must be given".to_string()),
+        }
+    }
+}
+
+/// Distribution of the gas used by transactions, selected with the `distribution` key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum GasConfig {
+    /// Each transaction uses one of `gas_used`, with probabilities proportional to `weights`
+    Categorical {
+        gas_used: Vec<u64>,
+        weights: Vec<f64>,
+    },
+    /// Each transaction uses the gas of a transaction drawn from the CSV file at `path`
+    Empirical { path: PathBuf },
+}
+
+impl GasConfig {
+    pub fn build(&self) -> Result<GasUsage, String> {
+        match self {
+            GasConfig::Categorical { gas_used, weights } => {
+                GasUsage::categorical(gas_used.clone(), weights)
+            }
+            GasConfig::Empirical { path } => GasUsage::from_csv(path),
+        }
+    }
+}
+
+/// Synthetic token price path, generated with the random number generator of the simulation so
+/// that every seed gives a different path
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenPriceConfig {
+    /// Token price at the start, in fiat
+    pub initial_price: f64,
+    /// Annualized drift of the token price
+    #[serde(default)]
+    pub drift: f64,
+    /// Time between two points of the path [second]
+    #[serde(default = "default_token_price_interval")]
+    pub interval: u64,
+    #[serde(flatten)]
+    pub model: TokenPriceModel,
+}
+
+impl TokenPriceConfig {
+    pub fn build(&self) -> Result<TokenPriceGenerator, String> {
+        TokenPriceGenerator::new(
+            self.initial_price,
+            self.drift,
+            self.interval,
+            self.model.clone(),
+        )
+    }
+}
+
+/// On-chain price oracle, publishing the median of the token prices observed by its reporters
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OracleConfig {
+    /// Time between two observations of the token price [second], every block if 0
+    #[serde(default)]
+    pub update_interval: u64,
+    /// Time between the observation of the token price and its publication [second]
+    #[serde(default)]
+    pub delay: u64,
+    /// Standard deviation of the relative error of each reporter
+    #[serde(default)]
+    pub noise: f64,
+    /// Number of reporters
+    #[serde(default = "default_reporters")]
+    pub reporters: u64,
+}
+
+impl OracleConfig {
+    pub fn build(&self) -> PriceOracle {
+        PriceOracle::new(self.update_interval, self.delay, self.noise, self.reporters)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TtlUnit {
+    #[default]
+    Blocks,
+    Seconds,
+}
+
+impl TtlUnit {
+    pub fn ttl(&self, value: u64) -> Ttl {
+        match self {
+            TtlUnit::Blocks => Ttl::Blocks(value),
+            TtlUnit::Seconds => Ttl::Seconds(value),
+        }
+    }
+}
+
+fn default_label() -> String {
+    "default".to_string()
+}
+
+fn default_output_dir() -> PathBuf {
+    PathBuf::from("out")
+}
+
+fn default_interp_resolution() -> u64 {
+    5_000
+}
+
+fn default_txpool_size() -> usize {
+    1_000_000
+}
+
+fn default_token_price_interval() -> u64 {
+    3_600
+}
+
+fn default_token_price_time_scale() -> f64 {
+    1.
+}
+
+fn default_reporters() -> u64 {
+    1
+}
+
+fn default_replicas() -> u64 {
+    1
+}
+
+fn default_elasticity_multiplier() -> u64 {
+    2
+}
+
+fn default_base_fee_max_change_denominator() -> u64 {
+    8
+}
+
+fn default_bid_ratio() -> f64 {
+    1.
+}
+
+/// Message of an error on a key whose value is outside of its valid range
+fn invalid_value(key: &str, expected: &str) -> String {
+    format!("invalid value for key `{}`: {}", key, expected)
+}
+
+/// Error in a config file
+#[derive(Debug)]
+pub struct ConfigError {
+    path: PathBuf,
+    message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigError {
+    pub fn new(path: &Path, message: String) -> ConfigError {
+        ConfigError {
+            path: path.to_path_buf(),
+            message,
+        }
+    }
+}
+
+impl SimulatorConfig {
+    /// Reads a config file, printing a warning for every key that is not recognized
+    pub fn from_file(path: &Path) -> Result<SimulatorConfig, ConfigError> {
+        let raw = SimulatorConfig::read_value(path)?;
+        let config = SimulatorConfig::from_value(&raw, path)?;
+
+        for key in config.unknown_keys(&raw) {
+            eprintln!(
+                "Warning: {}: unknown key `{}` is ignored",
+                path.display(),
+                key
+            );
+        }
+
+        Ok(config)
+    }
+
+    /// Reads a config file into an untyped value, with the default mechanism filled in
+    pub fn read_value(path: &Path) -> Result<serde_json::Value, ConfigError> {
+        let error = |e: config::ConfigError| ConfigError::new(path, e.to_string());
+
+        let mut settings = config::Config::default();
+        settings.merge(config::File::from(path)).map_err(error)?;
+        settings
+            .set_default("mechanism", "autoprice")
+            .map_err(error)?;
+
+        settings.try_into().map_err(error)
+    }
+
+    /// Builds a config from the untyped value read from the config file at `path`, resolving
+    /// relative paths against the directory of the config file
+    pub fn from_value(
+        raw: &serde_json::Value,
+        path: &Path,
+    ) -> Result<SimulatorConfig, ConfigError> {
+        let error = |e: config::ConfigError| ConfigError::new(path, e.to_string());
+        let mut config: SimulatorConfig = config::Config::try_from(raw)
+            .and_then(|settings| settings.try_into())
+            .map_err(error)?;
+
+        let root_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.resolve_paths(root_dir);
+
+        let invalid =
+            |key: &str, expected: &str| ConfigError::new(path, invalid_value(key, expected));
+        if config.block_gas_limit == 0 {
+            return Err(invalid("block_gas_limit", "must be positive"));
+        }
+        if config.block_time == 0 {
+            return Err(invalid("block_time", "must be positive"));
+        }
+        if config.interp_resolution < 2 {
+            return Err(invalid("interp_resolution", "must be at least 2"));
+        }
+        if config.replicas == 0 {
+            return Err(invalid("replicas", "must be at least 1"));
+        }
+
+        match &config.user_classes {
+            None => config.users.validate(config.interp_resolution),
+            Some(user_classes) => config.validate_user_classes(user_classes),
+        }
+        .map_err(|e| ConfigError::new(path, e))?;
+        if config.token_price_path.is_some() && config.token_price.is_some() {
+            return Err(ConfigError::new(
+                path,
+                "at most one of `token_price_path` and `token_price` can be given".to_string(),
+            ));
+        }
+        if config.token_price_start.is_some() && config.token_price_offset.is_some() {
+            return Err(ConfigError::new(
+                path,
+                "at most one of `token_price_start` and `token_price_offset` can be given"
+                    .to_string(),
+            ));
+        }
+        if config.token_price_time_scale <= 0. {
+            return Err(invalid("token_price_time_scale", "must be positive"));
+        }
+        let token_price = match &config.token_price_path {
+            Some(token_price_path) => Some(
+                LinearInterpolator::from_csv(token_price_path)
+                    .map_err(|e| ConfigError::new(path, format!("token_price_path: {}", e)))?,
+            ),
+            None => None,
+        };
+        if let (OutOfRange::Error, Some(token_price)) =
+            (config.token_price_out_of_range, &token_price)
+        {
+            let n_blocks = config.demand_profiles().iter().map(Vec::len).max();
+            let duration = n_blocks.unwrap_or(0).saturating_sub(1) as u64 * config.block_time;
+            config
+                .token_price_alignment()
+                .check_coverage(token_price, duration)
+                .map_err(|e| ConfigError::new(path, format!("token_price_out_of_range: {}", e)))?;
+        }
+        if let Some(token_price) = &config.token_price {
+            token_price
+                .build()
+                .map_err(|e| ConfigError::new(path, format!("token_price: {}", e)))?;
+        }
+        if let Some(oracle) = &config.oracle {
+            if oracle.noise < 0. {
+                return Err(invalid("oracle.noise", "must not be negative"));
+            }
+            if oracle.reporters == 0 {
+                return Err(invalid("oracle.reporters", "must be positive"));
+            }
+        }
+        config
+            .mechanism
+            .validate(config.block_gas_limit)
+            .map_err(|e| ConfigError::new(path, e))?;
+        if let Arrivals::NegativeBinomial { dispersion } = config.arrivals {
+            if dispersion <= 0. {
+                return Err(invalid("arrivals.dispersion", "must be positive"));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Checks the classes given by `user_classes`, which replace the single class described at
+    /// the top level
+    fn validate_user_classes(&self, user_classes: &[UserClassConfig]) -> Result<(), String> {
+        let users = &self.users;
+        if users.demand_curve_path.is_some()
+            || users.demand_curve.is_some()
+            || users.demand_curve_keyframes.is_some()
+            || users.demand_profile_path.is_some()
+            || users.demand_profile.is_some()
+            || users.tx_gas_used.is_some()
+            || users.tx_gas.is_some()
+            || users.tx_ttl.is_some()
+            || users.patience.is_some()
+        {
+            return Err(
+                "with `user_classes`, the demand curve, demand profile, gas usage, time-to-live \
+                 and patience must be given in each class"
+                    .to_string(),
+            );
+        }
+        if user_classes.is_empty() {
+            return Err("`user_classes` must not be empty".to_string());
+        }
+
+        for (i, user_class) in user_classes.iter().enumerate() {
+            if user_classes[..i]
+                .iter()
+                .any(|other| other.label == user_class.label)
+            {
+                return Err(format!(
+                    "user_classes[{}]: label `{}` is already used",
+                    i, user_class.label
+                ));
+            }
+            user_class
+                .validate(self.interp_resolution)
+                .map_err(|e| format!("user_classes[{}]: {}", i, e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the keys in `raw` which are not recognized
+    pub fn unknown_keys(&self, raw: &serde_json::Value) -> Vec<String> {
+        unknown_keys(raw, &serde_json::to_value(self).unwrap(), "")
+    }
+
+    /// Makes relative paths relative to `root_dir`
+    pub fn resolve_paths(&mut self, root_dir: &Path) {
+        self.output_dir = root_dir.join(&self.output_dir);
+        self.token_price_path = self.token_price_path.as_ref().map(|p| root_dir.join(p));
+        self.users.resolve_paths(root_dir);
+        for user_class in self.user_classes.iter_mut().flatten() {
+            user_class.resolve_paths(root_dir);
+        }
+    }
+
+    /// Mapping of the time of the simulation to timestamps of the token price data
+    pub fn token_price_alignment(&self) -> TimeAlignment {
+        TimeAlignment {
+            start: self.token_price_start,
+            offset: self.token_price_offset.unwrap_or(0.),
+            time_scale: self.token_price_time_scale,
+            out_of_range: self.token_price_out_of_range,
+        }
+    }
+
+    /// Configs of the classes of users, either given by `user_classes` or the single class
+    /// described at the top level
+    pub fn user_class_configs(&self) -> &[UserClassConfig] {
+        match &self.user_classes {
+            Some(user_classes) => user_classes,
+            None => std::slice::from_ref(&self.users),
+        }
+    }
+
+    /// Classes of users, reading their input files
+    pub fn user_classes(&self) -> Vec<UserClass> {
+        self.user_class_configs()
+            .iter()
+            .map(|user_class| user_class.build(self.interp_resolution))
+            .collect()
+    }
+
+    /// Number of users of each class in each block
+    pub fn demand_profiles(&self) -> Vec<Vec<u64>> {
+        self.user_class_configs()
+            .iter()
+            .map(|user_class| user_class.demand_profile(self.block_time))
+            .collect()
+    }
+}
+
+impl UserClassConfig {
+    /// Checks that exactly one source is given for each input of the class, and that the
+    /// parametric ones are valid
+    fn validate(&self, interp_resolution: u64) -> Result<(), String> {
+        let n_demand_curves = [
+            self.demand_curve_path.is_some(),
+            self.demand_curve.is_some(),
+            self.demand_curve_keyframes.is_some(),
+        ]
+        .iter()
+        .filter(|&&given| given)
+        .count();
+        if n_demand_curves != 1 {
+            return Err(
+                "exactly one of `demand_curve_path`, `demand_curve` and `demand_curve_keyframes` \
+                 must be given"
+                    .to_string(),
+            );
+        }
+        if let Some(keyframes) = &self.demand_curve_keyframes {
+            if keyframes.is_empty() {
+                return Err("`demand_curve_keyframes` must not be empty".to_string());
+            }
+            if keyframes.windows(2).any(|w| w[0].time >= w[1].time) {
+                return Err("`demand_curve_keyframes` must be sorted by time".to_string());
+            }
+            for (i, keyframe) in keyframes.iter().enumerate() {
+                if keyframe.path.is_some() == keyframe.curve.is_some() {
+                    return Err(format!(
+                        "demand_curve_keyframes[{}]: exactly one of `path` and `curve` must be \
+                         given",
+                        i
+                    ));
+                }
+                match (&keyframe.path, &keyframe.curve) {
+                    (Some(path), _) => DemandCurve::from_csv(path, interp_resolution).map(drop),
+                    (None, Some(curve)) => curve.validate(interp_resolution),
+                    (None, None) => Ok(()),
+                }
+                .map_err(|e| format!("demand_curve_keyframes[{}]: {}", i, e))?;
+            }
+        }
+        if let Some(path) = &self.demand_curve_path {
+            DemandCurve::from_csv(path, interp_resolution)
+                .map_err(|e| format!("demand_curve_path: {}", e))?;
+        }
+        if let Some(demand_curve) = &self.demand_curve {
+            demand_curve
+                .validate(interp_resolution)
+                .map_err(|e| format!("demand_curve: {}", e))?;
+        }
+        if self.demand_profile_path.is_some() == self.demand_profile.is_some() {
+            return Err(
+                "exactly one of `demand_profile_path` and `demand_profile` must be given"
+                    .to_string(),
+            );
+        }
+        if let Some(path) = &self.demand_profile_path {
+            read_demand_profile(path).map_err(|e| format!("demand_profile_path: {}", e))?;
+        }
+        if self.tx_gas_used.is_some() == self.tx_gas.is_some() {
+            return Err("exactly one of `tx_gas_used` and `tx_gas` must be given".to_string());
+        }
+        if let Some(tx_gas) = &self.tx_gas {
+            tx_gas.build().map_err(|e| format!("tx_gas: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Makes relative paths relative to `root_dir`
+    fn resolve_paths(&mut self, root_dir: &Path) {
+        self.demand_curve_path = self.demand_curve_path.as_ref().map(|p| root_dir.join(p));
+        for keyframe in self.demand_curve_keyframes.iter_mut().flatten() {
+            keyframe.path = keyframe.path.as_ref().map(|p| root_dir.join(p));
+        }
+        self.demand_profile_path = self.demand_profile_path.as_ref().map(|p| root_dir.join(p));
+        if let Some(GasConfig::Empirical { path }) = self.tx_gas.as_mut() {
+            *path = root_dir.join(&path);
+        }
+    }
+
+    /// Builds the class, reading its input files
+    pub fn build(&self, interp_resolution: u64) -> UserClass {
+        let mut user_class = UserClass::new(
+            self.label.clone(),
+            self.demand_curve(interp_resolution),
+            self.tx_gas(),
+        );
+        if let Some(tx_ttl) = self.tx_ttl() {
+            user_class = user_class.with_tx_ttl(tx_ttl);
+        }
+        if let Some(patience) = self.patience {
+            user_class = user_class.with_patience(patience);
+        }
+        user_class
+    }
+
+    /// Demand curve, either read from `demand_curve_path`, parametric or keyframed
+    pub fn demand_curve(&self, interp_resolution: u64) -> KeyframedDemandCurve {
+        let build =
+            |result: Result<DemandCurve, String>| result.unwrap_or_else(|e| panic!("{}", e));
+
+        if let Some(path) = &self.demand_curve_path {
+            build(DemandCurve::from_csv(path, interp_resolution)).into()
+        } else if let Some(demand_curve) = &self.demand_curve {
+            build(demand_curve.build(interp_resolution)).into()
+        } else if let Some(keyframes) = &self.demand_curve_keyframes {
+            let keyframes = keyframes
+                .iter()
+                .map(|keyframe| (keyframe.time, build(keyframe.build(interp_resolution))))
+                .collect();
+            KeyframedDemandCurve::new(keyframes, self.demand_curve_transition)
+        } else {
+            panic!("No demand curve given")
+        }
+    }
+
+    /// Number of users in each block, either read from `demand_profile_path` or generated for
+    /// blocks produced every `block_time` seconds
+    pub fn demand_profile(&self, block_time: u64) -> Vec<u64> {
+        match (&self.demand_profile_path, &self.demand_profile) {
+            (Some(path), _) => read_demand_profile(path).unwrap_or_else(|e| panic!("{}", e)),
+            (None, Some(profile)) => profile.generate(block_time),
+            (None, None) => panic!("No demand profile given"),
+        }
+    }
+
+    /// Distribution of the gas used by transactions, either constant or given by `tx_gas`
+    pub fn tx_gas(&self) -> GasUsage {
+        match &self.tx_gas {
+            Some(tx_gas) => tx_gas.build().unwrap_or_else(|e| panic!("{}", e)),
+            None => GasUsage::Constant(self.tx_gas_used.unwrap_or_default()),
+        }
+    }
+
+    pub fn tx_ttl(&self) -> Option<Ttl> {
+        self.tx_ttl.map(|ttl| self.tx_ttl_unit.ttl(ttl))
+    }
+}
+
+impl MechanismConfig {
+    /// Checks that the parameters of the mechanism are valid for blocks of `block_gas_limit` gas
+    fn validate(&self, block_gas_limit: u64) -> Result<(), String> {
+        let positive = |key: &str, value: f64| {
+            if value > 0. && value.is_finite() {
+                Ok(())
+            } else {
+                Err(invalid_value(key, "must be positive"))
+            }
+        };
+        let non_negative = |key: &str, value: f64| {
+            if value >= 0. && value.is_finite() {
+                Ok(())
+            } else {
+                Err(invalid_value(key, "must not be negative"))
+            }
+        };
+        // Mechanisms adjusting the price every `control_range` blocks to reach `target_fullness`
+        let controller = |control_range: u64, target_fullness: f64| {
+            if control_range == 0 {
+                return Err(invalid_value("control_range", "must be positive"));
+            }
+            if !(0. ..=1.).contains(&target_fullness) {
+                return Err(invalid_value("target_fullness", "must be between 0 and 1"));
+            }
+            Ok(())
+        };
+
+        match *self {
+            MechanismConfig::AutoPrice {
+                initial_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            } => {
+                controller(control_range, target_fullness)?;
+                // The price is adjusted by a factor, so it would never leave 0
+                positive("initial_price", initial_price as f64)?;
+                non_negative("price_adjustment_rate", price_adjustment_rate)
+            }
+            MechanismConfig::FiatAutoPrice {
+                initial_fiat_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            } => {
+                controller(control_range, target_fullness)?;
+                positive("initial_fiat_price", initial_fiat_price)?;
+                non_negative("price_adjustment_rate", price_adjustment_rate)
+            }
+            MechanismConfig::Pid {
+                initial_price,
+                control_range,
+                target_fullness,
+                proportional_gain,
+                integral_gain,
+                derivative_gain,
+                integral_limit,
+            } => {
+                controller(control_range, target_fullness)?;
+                // The controller output scales the initial price
+                positive("initial_price", initial_price as f64)?;
+                non_negative("proportional_gain", proportional_gain)?;
+                non_negative("integral_gain", integral_gain)?;
+                non_negative("derivative_gain", derivative_gain)?;
+                integral_limit.map_or(Ok(()), |limit| non_negative("integral_limit", limit))
+            }
+            MechanismConfig::Eip1559 {
+                elasticity_multiplier,
+                base_fee_max_change_denominator,
+                priority_fee_ratio,
+                ..
+            } => {
+                if elasticity_multiplier == 0 {
+                    return Err(invalid_value("elasticity_multiplier", "must be positive"));
+                }
+                if elasticity_multiplier > block_gas_limit {
+                    return Err(invalid_value(
+                        "elasticity_multiplier",
+                        "must not exceed `block_gas_limit`, so that the gas target is positive",
+                    ));
+                }
+                if base_fee_max_change_denominator == 0 {
+                    return Err(invalid_value(
+                        "base_fee_max_change_denominator",
+                        "must be positive",
+                    ));
+                }
+                non_negative("priority_fee_ratio", priority_fee_ratio)
+            }
+            MechanismConfig::FirstPrice { bid_ratio, .. } => positive("bid_ratio", bid_ratio),
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn FeeMechanism> {
+        match *self {
+            MechanismConfig::AutoPrice {
+                initial_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            } => Box::new(AutoPrice::new(
+                initial_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            )),
+            MechanismConfig::FiatAutoPrice {
+                initial_fiat_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            } => Box::new(FiatAutoPrice::new(
+                initial_fiat_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            )),
+            MechanismConfig::Pid {
+                initial_price,
+                control_range,
+                target_fullness,
+                proportional_gain,
+                integral_gain,
+                derivative_gain,
+                integral_limit,
+            } => Box::new(Pid::new(
+                initial_price,
+                control_range,
+                target_fullness,
+                proportional_gain,
+                integral_gain,
+                derivative_gain,
+                integral_limit,
+            )),
+            MechanismConfig::Eip1559 {
+                initial_price,
+                elasticity_multiplier,
+                base_fee_max_change_denominator,
+                priority_fee_ratio,
+            } => Box::new(Eip1559::new(
+                initial_price,
+                elasticity_multiplier,
+                base_fee_max_change_denominator,
+                priority_fee_ratio,
+            )),
+            MechanismConfig::FirstPrice {
+                min_gas_price,
+                bid_ratio,
+            } => Box::new(FirstPrice::new(min_gas_price, bid_ratio)),
+        }
+    }
+}
+
+/// Returns the keys in `raw` which do not appear in `known`, recursing into tables and arrays
+fn unknown_keys(raw: &serde_json::Value, known: &serde_json::Value, prefix: &str) -> Vec<String> {
+    use serde_json::Value;
+
+    match (raw, known) {
+        (Value::Object(raw), Value::Object(known)) => raw
+            .iter()
+            .flat_map(|(key, value)| {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match known.get(key) {
+                    Some(known_value) => unknown_keys(value, known_value, &path),
+                    None => vec![path],
+                }
+            })
+            .collect(),
+        (Value::Array(raw), Value::Array(known)) => raw
+            .iter()
+            .zip(known.iter())
+            .enumerate()
+            .flat_map(|(i, (value, known_value))| {
+                unknown_keys(value, known_value, &format!("{}[{}]", prefix, i))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unknown_keys, ConfigError, SimulatorConfig};
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::Path;
+
+    /// Raw single-class config with a first-price mechanism, in which the keys of `overrides` are
+    /// set, or removed if null
+    fn raw_config(overrides: Value) -> Value {
+        let mut raw = json!({
+            "block_gas_limit": 10,
+            "block_time": 600,
+            "mechanism": "first_price",
+            "min_gas_price": 1,
+            "demand_curve": {"shape": "linear", "min_price": 0, "max_price": 100},
+            "demand_profile": {"n_blocks": 2, "components": [{"type": "constant", "value": 10}]},
+            "tx_gas_used": 1,
+        });
+        let raw_map = raw.as_object_mut().unwrap();
+        for (key, value) in overrides.as_object().unwrap() {
+            if value.is_null() {
+                raw_map.remove(key);
+            } else {
+                raw_map.insert(key.clone(), value.clone());
+            }
+        }
+        raw
+    }
+
+    fn config(overrides: Value) -> Result<SimulatorConfig, ConfigError> {
+        SimulatorConfig::from_value(&raw_config(overrides), Path::new("config.toml"))
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let raw = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [{"f": 4, "g": 5}], "h": 6});
+        let known = json!({"a": 1, "b": {"c": 2}, "e": [{"f": 4}]});
+
+        assert_eq!(unknown_keys(&raw, &known, ""), vec!["b.d", "e[0].g", "h"]);
+    }
+
+    #[test]
+    fn test_user_classes() {
+        let user_class = |label: &str| {
+            json!({
+                "label": label,
+                "demand_curve": {"shape": "linear", "min_price": 0, "max_price": 100},
+                "demand_profile": {"n_blocks": 2, "components": [{"type": "constant", "value": 10}]},
+                "tx_gas_used": 21_000,
+            })
+        };
+        // The classes replace the single class of the base config
+        let with_classes = |user_classes: Vec<Value>| {
+            config(json!({
+                "block_gas_limit": 1_000_000,
+                "demand_curve": null,
+                "demand_profile": null,
+                "tx_gas_used": null,
+                "user_classes": user_classes,
+            }))
+        };
+
+        let two_classes = with_classes(vec![user_class("retail"), user_class("bots")]).unwrap();
+        let labels: Vec<&str> = two_classes
+            .user_class_configs()
+            .iter()
+            .map(|user_class| user_class.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["retail", "bots"]);
+        assert_eq!(two_classes.demand_profiles(), vec![vec![10, 10]; 2]);
+
+        assert!(with_classes(vec![user_class("retail"), user_class("retail")]).is_err());
+        assert!(with_classes(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_mechanism_validation() {
+        let eip1559 = |elasticity_multiplier: u64, base_fee_max_change_denominator: u64| {
+            config(json!({
+                "mechanism": "eip1559",
+                "initial_price": 1,
+                "priority_fee_ratio": 0.1,
+                "elasticity_multiplier": elasticity_multiplier,
+                "base_fee_max_change_denominator": base_fee_max_change_denominator,
+            }))
+        };
+
+        assert!(eip1559(2, 8).is_ok());
+        let error = eip1559(0, 8).unwrap_err().to_string();
+        assert!(error.contains("`elasticity_multiplier`"), "{}", error);
+        // A gas target of 0 gas
+        assert!(eip1559(20, 8).is_err());
+        let error = eip1559(2, 0).unwrap_err().to_string();
+        assert!(
+            error.contains("`base_fee_max_change_denominator`"),
+            "{}",
+            error
+        );
+
+        let autoprice = |control_range: u64, target_fullness: f64| {
+            config(json!({
+                "mechanism": "autoprice",
+                "initial_price": 1,
+                "control_range": control_range,
+                "target_fullness": target_fullness,
+                "price_adjustment_rate": 0.01,
+            }))
+        };
+        assert!(autoprice(144, 0.65).is_ok());
+        assert_eq!(
+            autoprice(144, 1.5).unwrap_err().to_string(),
+            "config.toml: invalid value for key `target_fullness`: must be between 0 and 1"
+        );
+        assert!(autoprice(0, 0.65).is_err());
+
+        let pid = |derivative_gain: f64| {
+            config(json!({
+                "mechanism": "pid",
+                "initial_price": 1,
+                "control_range": 144,
+                "target_fullness": 0.65,
+                "proportional_gain": 0.5,
+                "derivative_gain": derivative_gain,
+            }))
+        };
+        assert!(pid(0.).is_ok());
+        assert!(pid(-0.1).is_err());
+
+        // Prices scaled from an initial price of 0 would stay at 0
+        for mechanism in &["autoprice", "pid"] {
+            let error = config(json!({
+                "mechanism": mechanism,
+                "initial_price": 0,
+                "control_range": 144,
+                "target_fullness": 0.65,
+                "price_adjustment_rate": 0.01,
+                "proportional_gain": 0.5,
+            }))
+            .unwrap_err()
+            .to_string();
+            assert!(error.contains("`initial_price`"), "{}", error);
+        }
+
+        let replicas = |replicas: u64| config(json!({ "replicas": replicas }));
+        assert!(replicas(1).is_ok());
+        assert_eq!(
+            replicas(0).unwrap_err().to_string(),
+            "config.toml: invalid value for key `replicas`: must be at least 1"
+        );
+    }
+
+    #[test]
+    fn test_input_files() {
+        let root_dir = std::env::temp_dir().join(format!("config_{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        fs::write(root_dir.join("curve.csv"), "price,quantity\n0,100\n100,0\n").unwrap();
+        fs::write(root_dir.join("profile.csv"), "10\nten\n").unwrap();
+        fs::write(root_dir.join("token_price.csv"), "time,price\n").unwrap();
+
+        let config = |key: &str, file: &str| {
+            let mut overrides = json!({"demand_curve": null, "demand_curve_path": "curve.csv"});
+            if key == "demand_profile_path" {
+                overrides["demand_profile"] = Value::Null;
+            }
+            overrides[key] = json!(file);
+            SimulatorConfig::from_value(&raw_config(overrides), &root_dir.join("config.toml"))
+        };
+
+        assert!(config("demand_curve_path", "curve.csv").is_ok());
+        let errors = [
+            config("demand_curve_path", "missing.csv"),
+            config("demand_profile_path", "profile.csv"),
+            config("token_price_path", "token_price.csv"),
+        ];
+        fs::remove_dir_all(&root_dir).unwrap();
+
+        let keys = [
+            "demand_curve_path",
+            "demand_profile_path",
+            "token_price_path",
+        ];
+        let files = ["missing.csv", "profile.csv", "token_price.csv"];
+        for ((error, key), file) in errors.iter().zip(&keys).zip(&files) {
+            let error = error.as_ref().unwrap_err().to_string();
+            assert!(error.contains(key), "{}", error);
+            assert!(
+                error.contains(&root_dir.join(file).display().to_string()),
+                "{}",
+                error
+            );
+        }
+    }
+}
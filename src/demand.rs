@@ -1,20 +1,84 @@
 use csv;
 use std::fs::File;
+use std::path::Path;
 
 use is_sorted::IsSorted;
 use itertools_num::linspace;
 use ordered_float::OrderedFloat;
-use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::iter::{self, FromIterator};
 
 use crate::helper::*;
 
+/// Shape of a parametric demand curve, selected with the `shape` key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum DemandShape {
+    /// Quantity decreasing linearly with the price
+    Linear,
+    /// Quantity decreasing by a factor of e every `scale` increase in price
+    Exponential { scale: f64 },
+    /// Log-normally distributed willingness to pay, with the given median and standard deviation
+    /// of its logarithm
+    LogNormal { median: f64, sigma: f64 },
+    /// Pareto distributed willingness to pay, with a minimum of `scale` and a tail index of `alpha`
+    Pareto { scale: f64, alpha: f64 },
+    /// Quantity proportional to `price^-elasticity`
+    ConstantElasticity { elasticity: f64 },
+}
+
+impl DemandShape {
+    /// Share of users willing to pay at least `price`, up to an affine transformation
+    fn quantity(&self, price: f64) -> f64 {
+        match *self {
+            DemandShape::Linear => -price,
+            DemandShape::Exponential { scale } => (-price / scale).exp(),
+            DemandShape::LogNormal { median, sigma } => {
+                if price <= 0. {
+                    1.
+                } else {
+                    1. - normal_cdf((price / median).ln() / sigma)
+                }
+            }
+            DemandShape::Pareto { scale, alpha } => (scale / price.max(scale)).powf(alpha),
+            DemandShape::ConstantElasticity { elasticity } => price.powf(-elasticity),
+        }
+    }
+
+    fn validate(&self, min_price: u64) -> Result<(), String> {
+        let positive = |name: &str, value: f64| {
+            if value > 0. {
+                Ok(())
+            } else {
+                Err(format!(
+                    "invalid value for key `{}`: must be positive",
+                    name
+                ))
+            }
+        };
+        match *self {
+            DemandShape::Linear => Ok(()),
+            DemandShape::Exponential { scale } => positive("scale", scale),
+            DemandShape::LogNormal { median, sigma } => {
+                positive("median", median).and(positive("sigma", sigma))
+            }
+            DemandShape::Pareto { scale, alpha } => {
+                positive("scale", scale).and(positive("alpha", alpha))
+            }
+            DemandShape::ConstantElasticity { elasticity } => {
+                positive("elasticity", elasticity)?;
+                positive("min_price", min_price as f64)
+            }
+        }
+    }
+}
+
 pub struct DemandCurve {
     price: Vec<u64>,
     quantity: Vec<u64>,
     inverse_transform: Vec<u64>,
-    rng: ThreadRng,
 }
 
 impl DemandCurve {
@@ -28,13 +92,13 @@ impl DemandCurve {
         }
 
         let price_interp = Vec::from_iter(linspace(
-            price.iter().min().unwrap().clone() as f64,
-            price.iter().max().unwrap().clone() as f64,
+            *price.iter().min().unwrap() as f64,
+            *price.iter().max().unwrap() as f64,
             interp_resolution as usize,
         ));
 
-        let price_f64 = price.iter().map(|&x| x as f64).collect();
-        let quantity_f64 = quantity.iter().map(|&x| x as f64).collect();
+        let price_f64: Vec<f64> = price.iter().map(|&x| x as f64).collect();
+        let quantity_f64: Vec<f64> = quantity.iter().map(|&x| x as f64).collect();
 
         let interpolator1 = LinearInterpolator::new(&price_f64, &quantity_f64);
 
@@ -52,22 +116,29 @@ impl DemandCurve {
             .map(|x| x / quantity_interp_max)
             .collect();
 
-        let X = linspace(0., 1., interp_resolution as usize);
+        let x = linspace(0., 1., interp_resolution as usize);
 
         let interpolator2 = LinearInterpolator::new(&quantity_interp, &price_interp);
 
-        let inverse_transform: Vec<u64> = X.map(|x| interpolator2.interpolate(x) as u64).collect();
+        let inverse_transform: Vec<u64> = x.map(|x| interpolator2.interpolate(x) as u64).collect();
 
         DemandCurve {
-            price: price,
-            quantity: quantity,
-            inverse_transform: inverse_transform,
-            rng: rand::thread_rng(),
+            price,
+            quantity,
+            inverse_transform,
         }
     }
 
-    pub fn from_csv(path: &str, interp_resolution: u64) -> DemandCurve {
-        let file = File::open(path).expect("Couldn't open input CSV file");
+    /// Reads the price and quantity of the curve from the first two columns of a CSV file with
+    /// headers
+    pub fn from_csv(path: &Path, interp_resolution: u64) -> Result<DemandCurve, String> {
+        let file = File::open(path).map_err(|e| {
+            format!(
+                "couldn't open demand curve CSV file {}: {}",
+                path.display(),
+                e
+            )
+        })?;
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
             .from_reader(file);
@@ -75,20 +146,298 @@ impl DemandCurve {
         let (price, quantity): (Vec<_>, Vec<_>) = reader
             .records()
             .map(|record| {
-                let record = record.unwrap();
-                (
-                    record[0].parse::<u64>().unwrap(),
-                    record[1].parse::<u64>().unwrap(),
-                )
+                let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+                let parse = |i: usize| {
+                    let value = record.get(i).unwrap_or_default();
+                    value.trim().parse::<u64>().map_err(|e| {
+                        format!("{}: invalid value `{}`: {}", path.display(), value, e)
+                    })
+                };
+                Ok((parse(0)?, parse(1)?))
             })
+            .collect::<Result<Vec<(u64, u64)>, String>>()?
+            .into_iter()
             .unzip();
 
-        DemandCurve::new(price, quantity, interp_resolution)
+        if price.len() < 2 {
+            return Err(format!(
+                "{}: at least two points are needed",
+                path.display()
+            ));
+        }
+        Ok(DemandCurve::new(price, quantity, interp_resolution))
+    }
+
+    /// Creates a demand curve of the given shape, truncated to prices between `min_price` and
+    /// `max_price`
+    pub fn parametric(
+        shape: &DemandShape,
+        min_price: u64,
+        max_price: u64,
+        interp_resolution: u64,
+    ) -> Result<DemandCurve, String> {
+        let (price, quantity) =
+            DemandCurve::parametric_points(shape, min_price, max_price, interp_resolution)?;
+        Ok(DemandCurve::new(price, quantity, interp_resolution))
+    }
+
+    /// Checks the parameters of a parametric demand curve, without building its sample set
+    pub fn validate_parametric(
+        shape: &DemandShape,
+        min_price: u64,
+        max_price: u64,
+        interp_resolution: u64,
+    ) -> Result<(), String> {
+        DemandCurve::parametric_points(shape, min_price, max_price, interp_resolution).map(|_| ())
     }
 
-    pub fn sample_price(&mut self, size: usize) -> Vec<u64> {
-        iter::repeat_with(|| *(self.inverse_transform.choose(&mut self.rng).unwrap()))
+    /// Prices and quantities of a parametric demand curve
+    fn parametric_points(
+        shape: &DemandShape,
+        min_price: u64,
+        max_price: u64,
+        interp_resolution: u64,
+    ) -> Result<(Vec<u64>, Vec<u64>), String> {
+        if min_price >= max_price {
+            return Err("`min_price` must be lower than `max_price`".to_string());
+        }
+        shape.validate(min_price)?;
+
+        let n_points = interp_resolution.min(max_price - min_price + 1) as usize;
+        let mut prices: Vec<u64> = linspace(min_price as f64, max_price as f64, n_points)
+            .map(|p| p.round() as u64)
+            .collect();
+        prices.dedup();
+
+        // Rescale so that all users are willing to pay min_price and none max_price
+        let q_min = shape.quantity(min_price as f64);
+        let q_max = shape.quantity(max_price as f64);
+
+        let mut price = Vec::with_capacity(prices.len());
+        let mut quantity: Vec<u64> = Vec::with_capacity(prices.len());
+        for p in prices {
+            let q = ((shape.quantity(p as f64) - q_max) / (q_min - q_max) * 1e9).round() as u64;
+            let q = if p == max_price { 0 } else { q };
+            // Flat parts of the curve cannot be inverted
+            if quantity.last() != Some(&q) {
+                price.push(p);
+                quantity.push(q);
+            }
+        }
+
+        if quantity.len() < 2 {
+            return Err("demand curve is flat between `min_price` and `max_price`".to_string());
+        }
+
+        Ok((price, quantity))
+    }
+
+    pub fn price(&self) -> &[u64] {
+        &self.price
+    }
+
+    pub fn quantity(&self) -> &[u64] {
+        &self.quantity
+    }
+
+    pub fn sample_price<R: Rng + ?Sized>(&self, rng: &mut R, size: usize) -> Vec<u64> {
+        iter::repeat_with(|| *(self.inverse_transform.choose(rng).unwrap()))
             .take(size)
             .collect()
     }
 }
+
+/// How the demand curve changes between two keyframes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemandTransition {
+    /// Interpolate the willingness to pay of each quantile of users between the two curves
+    #[default]
+    Interpolate,
+    /// Keep the curve of the last keyframe until the next one
+    Switch,
+}
+
+/// Demand curves applying from given times, so that the distribution of the willingness to pay
+/// changes during a run. Before the first and after the last keyframe, their curve applies.
+pub struct KeyframedDemandCurve {
+    keyframes: Vec<(u64, DemandCurve)>,
+    transition: DemandTransition,
+}
+
+impl KeyframedDemandCurve {
+    /// Creates a demand curve from `(time, curve)` pairs sorted by time, where time is in seconds
+    /// since the start of the run. The curves need to be built with the same interpolation
+    /// resolution.
+    pub fn new(
+        keyframes: Vec<(u64, DemandCurve)>,
+        transition: DemandTransition,
+    ) -> KeyframedDemandCurve {
+        assert!(!keyframes.is_empty(), "No demand curve keyframes given");
+        assert!(
+            keyframes.windows(2).all(|w| w[0].0 < w[1].0),
+            "Demand curve keyframes must be sorted by time"
+        );
+        assert!(
+            keyframes
+                .iter()
+                .all(|(_, c)| c.inverse_transform.len() == keyframes[0].1.inverse_transform.len()),
+            "Demand curve keyframes must have the same interpolation resolution"
+        );
+
+        KeyframedDemandCurve {
+            keyframes,
+            transition,
+        }
+    }
+
+    /// Position of `time` among the keyframes, e.g. 1.5 halfway between the second and the third
+    /// keyframe. Whole when switching between keyframes.
+    pub fn keyframe(&self, time: u64) -> f64 {
+        let next = self.keyframes.iter().position(|&(t, _)| t > time);
+        match next {
+            None => (self.keyframes.len() - 1) as f64,
+            Some(0) => 0.,
+            Some(next) => {
+                let (t0, t1) = (self.keyframes[next - 1].0, self.keyframes[next].0);
+                let weight = match self.transition {
+                    DemandTransition::Interpolate => (time - t0) as f64 / (t1 - t0) as f64,
+                    DemandTransition::Switch => 0.,
+                };
+                (next - 1) as f64 + weight
+            }
+        }
+    }
+
+    /// Samples the willingness to pay of `size` users at `time`
+    pub fn sample_price<R: Rng + ?Sized>(&self, rng: &mut R, time: u64, size: usize) -> Vec<u64> {
+        let keyframe = self.keyframe(time);
+        let index = keyframe.floor() as usize;
+        let weight = keyframe - index as f64;
+
+        let curve = &self.keyframes[index].1;
+        if weight == 0. {
+            return curve.sample_price(rng, size);
+        }
+
+        let next_curve = &self.keyframes[index + 1].1;
+        let n_quantiles = curve.inverse_transform.len();
+        iter::repeat_with(|| {
+            let i = rng.gen_range(0, n_quantiles);
+            let price = (1. - weight) * curve.inverse_transform[i] as f64
+                + weight * next_curve.inverse_transform[i] as f64;
+            price as u64
+        })
+        .take(size)
+        .collect()
+    }
+}
+
+impl From<DemandCurve> for KeyframedDemandCurve {
+    fn from(demand_curve: DemandCurve) -> KeyframedDemandCurve {
+        KeyframedDemandCurve::new(vec![(0, demand_curve)], DemandTransition::Switch)
+    }
+}
+
+/// Reads the number of users in each block from a CSV file without headers
+pub fn read_demand_profile(path: &Path) -> Result<Vec<u64>, String> {
+    let file = File::open(path).map_err(|e| {
+        format!(
+            "couldn't open demand profile CSV file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(file);
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+            record[0].trim().parse::<u64>().map_err(|e| {
+                format!(
+                    "{}: invalid number of users `{}`: {}",
+                    path.display(),
+                    &record[0],
+                    e
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DemandCurve;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample_price_is_reproducible() {
+        let dc = DemandCurve::new(vec![0, 100, 200], vec![100, 50, 0], 100);
+
+        let sample1 = dc.sample_price(&mut StdRng::seed_from_u64(42), 50);
+        let sample2 = dc.sample_price(&mut StdRng::seed_from_u64(42), 50);
+
+        assert_eq!(sample1, sample2);
+        assert!(sample1.iter().all(|&x| x <= 200));
+    }
+
+    #[test]
+    fn test_parametric() {
+        use super::DemandShape;
+
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let linear = DemandCurve::parametric(&DemandShape::Linear, 100, 300, 1000).unwrap();
+        let sample = linear.sample_price(&mut rng, 10_000);
+        let mean = sample.iter().sum::<u64>() as f64 / sample.len() as f64;
+        assert!((mean - 200.).abs() < 5.);
+
+        let log_normal = DemandShape::LogNormal {
+            median: 1000.,
+            sigma: 0.5,
+        };
+        let curve = DemandCurve::parametric(&log_normal, 0, 100_000, 5000).unwrap();
+        let mut sample = curve.sample_price(&mut rng, 10_001);
+        sample.sort_unstable();
+        assert!((sample[5000] as f64 / 1000. - 1.).abs() < 0.05);
+
+        let pareto = DemandShape::Pareto {
+            scale: 0.,
+            alpha: 1.,
+        };
+        assert!(DemandCurve::parametric(&pareto, 0, 100, 100).is_err());
+        assert!(DemandCurve::parametric(&DemandShape::Linear, 100, 100, 100).is_err());
+    }
+
+    #[test]
+    fn test_keyframes() {
+        use super::{DemandTransition, KeyframedDemandCurve};
+
+        let keyframes = || {
+            vec![
+                (0, DemandCurve::new(vec![100, 101], vec![1, 0], 100)),
+                (100, DemandCurve::new(vec![300, 301], vec![1, 0], 100)),
+            ]
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let interpolated = KeyframedDemandCurve::new(keyframes(), DemandTransition::Interpolate);
+        assert_eq!(interpolated.keyframe(50), 0.5);
+        assert_eq!(interpolated.keyframe(200), 1.);
+        assert!(interpolated
+            .sample_price(&mut rng, 50, 100)
+            .iter()
+            .all(|&x| (200..=201).contains(&x)));
+
+        let switched = KeyframedDemandCurve::new(keyframes(), DemandTransition::Switch);
+        assert_eq!(switched.keyframe(50), 0.);
+        assert!(switched
+            .sample_price(&mut rng, 99, 100)
+            .iter()
+            .all(|&x| (100..=101).contains(&x)));
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::f64;
+
+const SECONDS_IN_DAY: f64 = 86_400.;
+
+/// Demand profile generated from a sum of components, configured with a `[demand_profile]` table.
+/// The number of users in a block is the sum of the level components (constant, Gaussian trend,
+/// step, ramp and spike), multiplied by the factor components (daily sinusoid and weekly
+/// seasonality).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DemandProfile {
+    /// Number of blocks to generate
+    pub n_blocks: u64,
+    /// Block of the components at which the profile starts
+    #[serde(default)]
+    pub offset: u64,
+    pub components: Vec<DemandComponent>,
+}
+
+/// Component of a demand profile, selected with the `type` key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DemandComponent {
+    /// Constant number of users
+    Constant { value: f64 },
+    /// Daily cycle, multiplying the demand by `1 + amplitude * sin(2π (block + phase) / period)`
+    DailySinusoid {
+        amplitude: f64,
+        /// Phase of the cycle [block]
+        #[serde(default)]
+        phase: f64,
+        /// Period of the cycle [block], one day by default
+        #[serde(default)]
+        period: Option<f64>,
+    },
+    /// Bell-shaped change in demand, peaking at `peak` users at block `center`
+    GaussianTrend { peak: f64, center: f64, width: f64 },
+    /// Change of `value` users from block `at` onwards
+    Step { at: u64, value: f64 },
+    /// Change in demand growing linearly from 0 at block `start` to `value` users at block `end`,
+    /// and held afterwards
+    Ramp { start: u64, end: u64, value: f64 },
+    /// Change of `value` users during `duration` blocks from block `at`
+    Spike { at: u64, duration: u64, value: f64 },
+    /// Weekly cycle, multiplying the demand by the factor of the day of the week, the first block
+    /// falling on the first day
+    WeeklySeasonality { factors: [f64; 7] },
+}
+
+impl DemandComponent {
+    /// Number of users added by a level component, or `None` for factor components
+    fn level(&self, block: f64) -> Option<f64> {
+        match *self {
+            DemandComponent::Constant { value } => Some(value),
+            DemandComponent::GaussianTrend {
+                peak,
+                center,
+                width,
+            } => Some(peak * (-(block - center).powi(2) / 2. / width.powi(2)).exp()),
+            DemandComponent::Step { at, value } => {
+                Some(if block >= at as f64 { value } else { 0. })
+            }
+            DemandComponent::Ramp { start, end, value } => {
+                let (start, end) = (start as f64, end as f64);
+                Some(if block <= start {
+                    0.
+                } else if block >= end {
+                    value
+                } else {
+                    value * (block - start) / (end - start)
+                })
+            }
+            DemandComponent::Spike {
+                at,
+                duration,
+                value,
+            } => Some(if block >= at as f64 && block < (at + duration) as f64 {
+                value
+            } else {
+                0.
+            }),
+            DemandComponent::DailySinusoid { .. } | DemandComponent::WeeklySeasonality { .. } => {
+                None
+            }
+        }
+    }
+
+    /// Factor applied by a factor component, or `None` for level components
+    fn factor(&self, block: f64, block_time: u64) -> Option<f64> {
+        let blocks_in_day = SECONDS_IN_DAY / block_time as f64;
+        match *self {
+            DemandComponent::DailySinusoid {
+                amplitude,
+                phase,
+                period,
+            } => {
+                let period = period.unwrap_or(blocks_in_day);
+                Some(1. + amplitude * (2. * f64::consts::PI / period * (block + phase)).sin())
+            }
+            DemandComponent::WeeklySeasonality { factors } => {
+                let day = (block / blocks_in_day).floor() as usize;
+                Some(factors[day % 7])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl DemandProfile {
+    /// Generates the number of users in each block, for blocks produced every `block_time` seconds
+    pub fn generate(&self, block_time: u64) -> Vec<u64> {
+        (self.offset..self.offset + self.n_blocks)
+            .map(|i| self.n_user(i as f64, block_time))
+            .collect()
+    }
+
+    fn n_user(&self, block: f64, block_time: u64) -> u64 {
+        let level: f64 = self.components.iter().filter_map(|c| c.level(block)).sum();
+        let factor: f64 = self
+            .components
+            .iter()
+            .filter_map(|c| c.factor(block, block_time))
+            .product();
+        // Negative demand is clamped to 0
+        (level * factor) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DemandComponent, DemandProfile};
+
+    #[test]
+    fn test_components() {
+        let profile = DemandProfile {
+            n_blocks: 6,
+            offset: 0,
+            components: vec![
+                DemandComponent::Constant { value: 100. },
+                DemandComponent::Step { at: 2, value: 50. },
+                DemandComponent::Ramp {
+                    start: 1,
+                    end: 3,
+                    value: 20.,
+                },
+                DemandComponent::Spike {
+                    at: 4,
+                    duration: 1,
+                    value: 1000.,
+                },
+            ],
+        };
+        assert_eq!(profile.generate(600), vec![100, 100, 160, 170, 1170, 170]);
+    }
+
+    #[test]
+    fn test_factors() {
+        let mut factors = [1.; 7];
+        factors[1] = 0.5;
+        let profile = DemandProfile {
+            n_blocks: 4,
+            offset: 0,
+            components: vec![
+                DemandComponent::Constant { value: 100. },
+                DemandComponent::DailySinusoid {
+                    amplitude: 0.5,
+                    phase: 0.,
+                    period: Some(4.),
+                },
+                DemandComponent::WeeklySeasonality { factors },
+            ],
+        };
+        // One block per day
+        assert_eq!(profile.generate(86_400), vec![100, 75, 100, 50]);
+    }
+}
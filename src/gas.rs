@@ -0,0 +1,93 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs::File;
+use std::path::Path;
+
+/// Distribution of the gas used by the transactions of the users
+#[derive(Clone, Debug)]
+pub enum GasUsage {
+    /// Every transaction uses the same amount of gas
+    Constant(u64),
+    /// Transactions use one of the given amounts of gas, with the given weights
+    Categorical {
+        gas_used: Vec<u64>,
+        index: WeightedIndex<f64>,
+    },
+    /// Transactions use the amount of gas of a transaction drawn from a sample
+    Empirical(Vec<u64>),
+}
+
+impl GasUsage {
+    pub fn categorical(gas_used: Vec<u64>, weights: &[f64]) -> Result<GasUsage, String> {
+        if gas_used.len() != weights.len() {
+            return Err("gas usages and weights must have the same length".to_string());
+        }
+        let index = WeightedIndex::new(weights).map_err(|e| e.to_string())?;
+        Ok(GasUsage::Categorical { gas_used, index })
+    }
+
+    /// Reads a sample of gas usages from the first column of a CSV file without headers
+    pub fn from_csv(path: &Path) -> Result<GasUsage, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("couldn't open gas usage CSV file {}: {}", path.display(), e))?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(file);
+
+        let sample = reader
+            .records()
+            .map(|record| {
+                let record = record.map_err(|e| e.to_string())?;
+                record[0]
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|e| format!("invalid gas usage `{}`: {}", &record[0], e))
+            })
+            .collect::<Result<Vec<u64>, String>>()?;
+
+        if sample.is_empty() {
+            return Err(format!("no gas usages in {}", path.display()));
+        }
+        Ok(GasUsage::Empirical(sample))
+    }
+
+    /// Amounts of gas of a categorical distribution, empty for the other distributions
+    pub fn categories(&self) -> &[u64] {
+        match self {
+            GasUsage::Categorical { gas_used, .. } => gas_used,
+            _ => &[],
+        }
+    }
+
+    /// Draws the gas used by a transaction
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        match self {
+            GasUsage::Constant(gas_used) => *gas_used,
+            GasUsage::Categorical { gas_used, index } => gas_used[index.sample(rng)],
+            GasUsage::Empirical(sample) => *sample.choose(rng).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GasUsage;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_categorical() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let gas = GasUsage::categorical(vec![21_000, 1_000_000], &[3., 1.]).unwrap();
+
+        let sample: Vec<u64> = (0..10_000).map(|_| gas.sample(&mut rng)).collect();
+        let n_small = sample.iter().filter(|&&x| x == 21_000).count();
+
+        assert!(sample.iter().all(|&x| x == 21_000 || x == 1_000_000));
+        assert!((n_small as f64 / 10_000. - 0.75).abs() < 0.02);
+
+        assert!(GasUsage::categorical(vec![21_000], &[1., 2.]).is_err());
+        assert!(GasUsage::categorical(vec![21_000], &[0.]).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use ordered_float::OrderedFloat;
+use std::fs::File;
+use std::path::Path;
 // use sorted_list::SortedList;
 
 pub struct LinearInterpolator {
@@ -9,7 +11,7 @@ pub struct LinearInterpolator {
 }
 
 impl LinearInterpolator {
-    pub fn new(x: &Vec<f64>, y: &Vec<f64>) -> LinearInterpolator {
+    pub fn new(x: &[f64], y: &[f64]) -> LinearInterpolator {
         assert!(x.len() == y.len());
 
         let xmax = *x.iter().max_by_key(|n| OrderedFloat(n.abs())).unwrap();
@@ -24,14 +26,46 @@ impl LinearInterpolator {
         LinearInterpolator {
             x: x_,
             y: y_,
-            xmax: xmax,
-            xmin: xmin,
+            xmax,
+            xmin,
         }
     }
 
-    pub fn interpolate(&self, a: f64) -> f64 {
-        let result: f64 = 0.;
+    /// Reads the interpolation points from the first two columns of a CSV file with headers
+    pub fn from_csv(path: &Path) -> Result<LinearInterpolator, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("couldn't open CSV file {}: {}", path.display(), e))?;
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(file);
+
+        let mut x: Vec<f64> = Vec::new();
+        let mut y: Vec<f64> = Vec::new();
+
+        for record in reader.records() {
+            let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+            let parse = |i: usize| {
+                let value = record.get(i).unwrap_or_default();
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("{}: invalid value `{}`: {}", path.display(), value, e))
+            };
+            x.push(parse(0)?);
+            y.push(parse(1)?);
+        }
+
+        if x.len() < 2 {
+            return Err(format!(
+                "{}: at least two points are needed",
+                path.display()
+            ));
+        }
+        Ok(LinearInterpolator::new(&x, &y))
+    }
 
+    pub fn interpolate(&self, a: f64) -> f64 {
         // // Linear search for the index
         // let mut idx: usize = 0;
         // for i in 0..self.x.len() - 1 {
@@ -52,14 +86,13 @@ impl LinearInterpolator {
     fn lower_bound_index(&self, a: f64) -> usize {
         assert!(self.check_bounds(a));
         let mut bottom: usize = 0;
-        let mut middle: usize = 0;
         let mut top: usize = self.x.len() - 1;
 
         loop {
             if top == bottom || top - 1 == bottom {
                 break;
             }
-            middle = ((top + bottom + 1) - (top + bottom + 1) % 2) / 2;
+            let middle = ((top + bottom + 1) - (top + bottom + 1) % 2) / 2;
             // println!("{} {} {}", bottom, middle, top);
 
             if self.x[bottom] <= a && a < self.x[middle] {
@@ -84,6 +117,35 @@ impl LinearInterpolator {
     }
 }
 
+/// Returns the `q`-th quantile (0 <= q <= 1) of sorted data, interpolating linearly between the
+/// closest ranks
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = q * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Cumulative distribution function of the standard normal distribution, using the approximation
+/// of the error function 7.1.26 in Abramowitz and Stegun, accurate to 1.5e-7
+pub fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / 2f64.sqrt();
+    let t = 1. / (1. + 0.327_591_1 * z);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1. - poly * (-z * z).exp();
+    if x >= 0. {
+        0.5 * (1. + erf)
+    } else {
+        0.5 * (1. - erf)
+    }
+}
+
 // pub fn linear_interpolation(x: &Vec<f64>, y: &Vec<f64>, a: f64) -> f64 {
 //     assert!(x.len() == y.len());
 //     let xmax = *x.iter().max_by_key(|n| OrderedFloat(n.abs())).unwrap();
@@ -102,13 +164,11 @@ impl LinearInterpolator {
 
 #[cfg(test)]
 mod tests {
-    use super::LinearInterpolator;
-    use std::fmt::Debug;
+    use super::{normal_cdf, quantile, LinearInterpolator};
 
     #[test]
     fn test_interpolate1() {
-        let interp =
-            LinearInterpolator::new(&vec![0., 1., 2., 4., 8.], &vec![0., 1., 3., 12., 13.]);
+        let interp = LinearInterpolator::new(&[0., 1., 2., 4., 8.], &[0., 1., 3., 12., 13.]);
 
         // interp.lower_bound_index(2.);
 
@@ -118,4 +178,22 @@ mod tests {
         assert_eq!(interp.interpolate(1.5), 2.);
         assert_eq!(interp.interpolate(2.), 3.);
     }
+
+    #[test]
+    fn test_quantile() {
+        let data = [1., 2., 3., 4., 5.];
+
+        assert_eq!(quantile(&data, 0.), Some(1.));
+        assert_eq!(quantile(&data, 0.5), Some(3.));
+        assert_eq!(quantile(&data, 0.875), Some(4.5));
+        assert_eq!(quantile(&data, 1.), Some(5.));
+        assert_eq!(quantile(&[], 0.5), None);
+    }
+
+    #[test]
+    fn test_normal_cdf() {
+        assert!((normal_cdf(0.) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+        assert!((normal_cdf(-1.96) - 0.025).abs() < 1e-4);
+    }
 }
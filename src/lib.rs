@@ -1,10 +1,26 @@
+pub mod arrivals;
+pub mod backlog;
+pub mod bands;
 pub mod block;
+pub mod config;
 pub mod demand;
+pub mod demand_profile;
+pub mod gas;
 pub mod helper;
+pub mod mechanism;
+pub mod oracle;
+pub mod packing;
 pub mod simulator;
 pub mod sorted_list;
+pub mod summary;
+pub mod sweep;
+pub mod token_price;
 pub mod transaction;
+pub mod tx_log;
+pub mod user_class;
 
+pub use crate::config::SimulatorConfig;
+pub use crate::mechanism::FeeMechanism;
 pub use crate::simulator::FeeMarketSimulator;
 
 #[cfg(test)]
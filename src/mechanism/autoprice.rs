@@ -0,0 +1,90 @@
+use stats::median;
+
+use crate::block::Block;
+use crate::mechanism::{FeeMechanism, MarketState};
+use crate::transaction::Transaction;
+
+/// Fixed gas price which is adjusted every `control_range` blocks. If the median fullness of the
+/// last `control_range` blocks is above `target_fullness`, the price is multiplied by
+/// `1 + price_adjustment_rate`, otherwise it is divided by it.
+pub struct AutoPrice {
+    price: u64,
+    control_range: u64,
+    target_fullness: f64,
+    price_adjustment_rate: f64,
+    control_fullness: f64,
+}
+
+impl AutoPrice {
+    pub fn new(
+        initial_price: u64,
+        control_range: u64,
+        target_fullness: f64,
+        price_adjustment_rate: f64,
+    ) -> AutoPrice {
+        AutoPrice {
+            price: initial_price,
+            control_range,
+            target_fullness,
+            price_adjustment_rate,
+            control_fullness: 0.,
+        }
+    }
+}
+
+impl FeeMechanism for AutoPrice {
+    fn update_price(&mut self, state: &MarketState) {
+        if let Some(control_fullness) = control_fullness(state, self.control_range) {
+            self.control_fullness = control_fullness;
+            let increase = self.control_fullness > self.target_fullness;
+            self.price =
+                adjust_price(self.price as f64, increase, self.price_adjustment_rate) as u64;
+        }
+    }
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        if wtp >= self.price as f64 {
+            Some(Transaction::new(gas_used, self.price))
+        } else {
+            None
+        }
+    }
+
+    fn gas_price(&self) -> u64 {
+        self.price
+    }
+
+    fn control_fullness(&self) -> f64 {
+        self.control_fullness
+    }
+
+    fn target_fullness(&self) -> Option<f64> {
+        Some(self.target_fullness)
+    }
+}
+
+/// Median fullness of the last `control_range` blocks, if the price is due to be adjusted before
+/// the block at `state.height`
+pub(crate) fn control_fullness(state: &MarketState, control_range: u64) -> Option<f64> {
+    if state.height == 0 || !state.height.is_multiple_of(control_range) {
+        return None;
+    }
+
+    let control_blocks = state.blocks.iter().rev().take(control_range as usize);
+
+    // let control_gas_used: u64 = control_blocks.iter().map(|&b| b.gas_used()).sum();
+    // let max_gas_used = control_blocks.len() as u64 * self.block_gas_limit;
+    // control_fullness = control_gas_used as f64 / max_gas_used as f64;
+
+    Some(median(control_blocks.map(Block::fullness)).expect("No blocks in the control range"))
+}
+
+/// Multiplies the price by `1 + price_adjustment_rate` if it should increase, and divides it by
+/// the same factor otherwise
+pub(crate) fn adjust_price(price: f64, increase: bool, price_adjustment_rate: f64) -> f64 {
+    if increase {
+        price * (1. + price_adjustment_rate)
+    } else {
+        price / (1. + price_adjustment_rate)
+    }
+}
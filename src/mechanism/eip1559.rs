@@ -0,0 +1,149 @@
+use crate::mechanism::{FeeMechanism, MarketState};
+use crate::packing::PackingStrategy;
+use crate::transaction::Transaction;
+
+/// Base fee which is updated every block according to EIP-1559. The targeted gas usage of a block
+/// is its gas limit divided by `elasticity_multiplier`, and the base fee changes by at most
+/// `1 / base_fee_max_change_denominator` between two blocks. Each user sets their max fee to their
+/// willingness to pay and attaches a max priority fee of `priority_fee_ratio` times it, so tips
+/// are not drawn at random but vary with the willingness to pay drawn from the demand curve.
+pub struct Eip1559 {
+    base_fee: u64,
+    elasticity_multiplier: u64,
+    base_fee_max_change_denominator: u64,
+    priority_fee_ratio: f64,
+    control_fullness: f64,
+}
+
+impl Eip1559 {
+    pub fn new(
+        initial_base_fee: u64,
+        elasticity_multiplier: u64,
+        base_fee_max_change_denominator: u64,
+        priority_fee_ratio: f64,
+    ) -> Eip1559 {
+        assert!(
+            elasticity_multiplier > 0,
+            "Elasticity multiplier must be positive"
+        );
+        assert!(
+            base_fee_max_change_denominator > 0,
+            "Base fee max change denominator must be positive"
+        );
+
+        Eip1559 {
+            base_fee: initial_base_fee,
+            elasticity_multiplier,
+            base_fee_max_change_denominator,
+            priority_fee_ratio,
+            control_fullness: 0.,
+        }
+    }
+}
+
+impl FeeMechanism for Eip1559 {
+    fn update_price(&mut self, state: &MarketState) {
+        if let Some(parent) = state.blocks.last() {
+            let gas_target = parent.gas_limit() / self.elasticity_multiplier;
+            self.control_fullness = parent.fullness();
+            self.base_fee = next_base_fee(
+                self.base_fee,
+                parent.gas_used(),
+                gas_target,
+                self.base_fee_max_change_denominator,
+            );
+        }
+    }
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        let max_fee = wtp as u64;
+        if max_fee >= self.base_fee {
+            let max_priority_fee = (max_fee as f64 * self.priority_fee_ratio) as u64;
+            Some(Transaction::new_eip1559(
+                gas_used,
+                max_fee,
+                max_priority_fee,
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn gas_price(&self) -> u64 {
+        self.base_fee
+    }
+
+    fn control_fullness(&self) -> f64 {
+        self.control_fullness
+    }
+
+    fn target_fullness(&self) -> Option<f64> {
+        Some(1. / self.elasticity_multiplier as f64)
+    }
+
+    fn base_fee(&self) -> u64 {
+        self.base_fee
+    }
+
+    /// Block producers order transactions by the tip they actually receive, which is capped by
+    /// the max fee minus the base fee
+    fn block_packing(&self) -> PackingStrategy {
+        PackingStrategy::FeeDensity
+    }
+}
+
+/// Computes the base fee of the next block from the gas used by its parent, as given in EIP-1559
+fn next_base_fee(
+    base_fee: u64,
+    gas_used: u64,
+    gas_target: u64,
+    base_fee_max_change_denominator: u64,
+) -> u64 {
+    if gas_used > gas_target {
+        let delta = base_fee as u128 * (gas_used - gas_target) as u128
+            / gas_target as u128
+            / base_fee_max_change_denominator as u128;
+        base_fee + (delta as u64).max(1)
+    } else {
+        let delta = base_fee as u128 * (gas_target - gas_used) as u128
+            / gas_target as u128
+            / base_fee_max_change_denominator as u128;
+        base_fee - delta as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_base_fee, Eip1559};
+    use crate::mechanism::FeeMechanism;
+    use crate::packing::BlockPacker;
+    use crate::transaction::{Transaction, TransactionPool};
+
+    #[test]
+    fn test_next_base_fee() {
+        // At target, the base fee stays the same
+        assert_eq!(next_base_fee(1000, 50, 50, 8), 1000);
+        // A full block increases the base fee by 1/8
+        assert_eq!(next_base_fee(1000, 100, 50, 8), 1125);
+        // An empty block decreases the base fee by 1/8
+        assert_eq!(next_base_fee(1000, 0, 50, 8), 875);
+        // The base fee increases by at least 1
+        assert_eq!(next_base_fee(1, 51, 50, 8), 2);
+    }
+
+    #[test]
+    fn test_block_packing() {
+        let eip1559 = Eip1559::new(100, 2, 8, 0.5);
+        let mut pool = TransactionPool::new(10);
+        pool.add_txs(vec![
+            // Highest max priority fee, but a tip of 10 under a base fee of 100
+            Transaction::new_eip1559(10, 110, 60),
+            Transaction::new_eip1559(10, 150, 50),
+        ]);
+
+        let mut packer = BlockPacker::new(eip1559.block_packing());
+        let included = pool.pop_block_txs(&mut packer, 10, eip1559.base_fee());
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].priority_fee(eip1559.base_fee()), 50);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::mechanism::autoprice::{adjust_price, control_fullness};
+use crate::mechanism::{FeeMechanism, MarketState};
+use crate::transaction::Transaction;
+
+/// AutoPrice whose controller state is a gas price in fiat rather than in native tokens. The fiat
+/// price is adjusted every `control_range` blocks like AutoPrice, and converted to tokens every
+/// block at the current token price, so that the price users face in fiat does not move with the
+/// token price between two adjustments.
+pub struct FiatAutoPrice {
+    fiat_price: f64,
+    price: u64,
+    control_range: u64,
+    target_fullness: f64,
+    price_adjustment_rate: f64,
+    control_fullness: f64,
+}
+
+impl FiatAutoPrice {
+    /// Creates the controller with an initial gas price in fiat, in the units of the demand curve
+    pub fn new(
+        initial_fiat_price: f64,
+        control_range: u64,
+        target_fullness: f64,
+        price_adjustment_rate: f64,
+    ) -> FiatAutoPrice {
+        FiatAutoPrice {
+            fiat_price: initial_fiat_price,
+            price: initial_fiat_price as u64,
+            control_range,
+            target_fullness,
+            price_adjustment_rate,
+            control_fullness: 0.,
+        }
+    }
+
+    /// Gas price in fiat targeted by the controller
+    pub fn fiat_price(&self) -> f64 {
+        self.fiat_price
+    }
+}
+
+impl FeeMechanism for FiatAutoPrice {
+    fn update_price(&mut self, state: &MarketState) {
+        if let Some(control_fullness) = control_fullness(state, self.control_range) {
+            self.control_fullness = control_fullness;
+            let increase = self.control_fullness > self.target_fullness;
+            self.fiat_price = adjust_price(self.fiat_price, increase, self.price_adjustment_rate);
+        }
+        // fiat/gas / fiat/token = token/gas
+        self.price = (self.fiat_price / state.token_price) as u64;
+    }
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        if wtp >= self.price as f64 {
+            Some(Transaction::new(gas_used, self.price))
+        } else {
+            None
+        }
+    }
+
+    fn gas_price(&self) -> u64 {
+        self.price
+    }
+
+    fn control_fullness(&self) -> f64 {
+        self.control_fullness
+    }
+
+    fn target_fullness(&self) -> Option<f64> {
+        Some(self.target_fullness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FiatAutoPrice;
+    use crate::block::Block;
+    use crate::mechanism::{FeeMechanism, MarketState};
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_fiat_peg() {
+        let mut mechanism = FiatAutoPrice::new(1000., 2, 0.5, 0.1);
+        let full_block = || {
+            let mut block = Block::new(10);
+            block.add_txs(vec![Transaction::new(10, 1)]);
+            block
+        };
+        let blocks = [full_block(), full_block()];
+        let state = |height: u64, token_price: f64| MarketState {
+            height,
+            time: height * 600,
+            token_price,
+            blocks: &blocks[..height as usize],
+        };
+
+        // The token price halves, so the gas price in tokens doubles
+        mechanism.update_price(&state(1, 0.5));
+        assert_eq!(mechanism.gas_price(), 2000);
+
+        // Full blocks raise the fiat price by 10%
+        mechanism.update_price(&state(2, 2.));
+        assert!((mechanism.fiat_price() - 1100.).abs() < 1e-9);
+        assert_eq!(mechanism.gas_price(), 550);
+    }
+}
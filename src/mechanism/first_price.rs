@@ -0,0 +1,69 @@
+use crate::block::Block;
+use crate::mechanism::{FeeMechanism, MarketState};
+use crate::transaction::Transaction;
+
+/// First-price auction where each user bids `bid_ratio` times their willingness to pay and pays
+/// their own bid when included. Bids below `min_gas_price` are not accepted.
+///
+/// As there is no single price, the gas price of a block is the median price paid in it, or
+/// `min_gas_price` if the block is empty.
+pub struct FirstPrice {
+    min_gas_price: u64,
+    bid_ratio: f64,
+}
+
+impl FirstPrice {
+    pub fn new(min_gas_price: u64, bid_ratio: f64) -> FirstPrice {
+        FirstPrice {
+            min_gas_price,
+            bid_ratio,
+        }
+    }
+}
+
+impl FeeMechanism for FirstPrice {
+    fn update_price(&mut self, _state: &MarketState) {}
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        let bid = (wtp * self.bid_ratio) as u64;
+        if bid >= self.min_gas_price {
+            Some(Transaction::new(gas_used, bid))
+        } else {
+            None
+        }
+    }
+
+    fn gas_price(&self) -> u64 {
+        self.min_gas_price
+    }
+
+    fn block_gas_price(&self, block: &Block) -> u64 {
+        block.median_price().unwrap_or(self.min_gas_price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FirstPrice;
+    use crate::block::Block;
+    use crate::mechanism::FeeMechanism;
+
+    #[test]
+    fn test_block_gas_price() {
+        let mechanism = FirstPrice::new(100, 0.5);
+        let mut block = Block::new(100);
+        assert_eq!(mechanism.block_gas_price(&block), 100);
+
+        let txs = [300., 500., 900.]
+            .iter()
+            .map(|&wtp| {
+                let mut tx = mechanism.price_tx(wtp, 10).unwrap();
+                tx.include(1, mechanism.charge(&tx));
+                tx
+            })
+            .collect();
+        block.add_txs(txs);
+        assert_eq!(mechanism.block_gas_price(&block), 250);
+        assert!(mechanism.price_tx(150., 10).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::block::Block;
+use crate::packing::PackingStrategy;
+use crate::transaction::Transaction;
+
+pub mod autoprice;
+pub mod eip1559;
+pub mod fiat_autoprice;
+pub mod first_price;
+pub mod pid;
+
+pub use self::autoprice::AutoPrice;
+pub use self::eip1559::Eip1559;
+pub use self::fiat_autoprice::FiatAutoPrice;
+pub use self::first_price::FirstPrice;
+pub use self::pid::{Pid, PidTerms};
+
+/// State of the market made available to a fee mechanism when it updates its price
+pub struct MarketState<'a> {
+    /// Height of the block about to be produced
+    pub height: u64,
+    /// Time of the block about to be produced [second]
+    pub time: u64,
+    /// Price of the token in fiat, 1 if no token price data is given
+    pub token_price: f64,
+    /// Blocks produced so far, oldest first
+    pub blocks: &'a [Block],
+}
+
+/// A rule that sets the price of gas and decides what transactions pay.
+///
+/// At every block, the simulator first calls `update_price`, then asks the mechanism to price the
+/// transaction of each user with `price_tx`. Transactions that can pay `base_fee` are included
+/// following the block packing strategy, `block_packing` unless configured otherwise, and each is
+/// charged `charge` per gas.
+pub trait FeeMechanism {
+    /// Updates the internal price before the block at `state.height` is produced
+    fn update_price(&mut self, state: &MarketState);
+
+    /// Returns the transaction sent by a user willing to pay `wtp` tokens per gas, or `None` if
+    /// the user is priced out
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction>;
+
+    /// Gas price set by the mechanism
+    fn gas_price(&self) -> u64;
+
+    /// Gas price of a block once produced, written to the output as `fixed_gas_price` and used
+    /// for the gas price statistics of the summary
+    fn block_gas_price(&self, _block: &Block) -> u64 {
+        self.gas_price()
+    }
+
+    /// Fullness the last price update was based on
+    fn control_fullness(&self) -> f64 {
+        0.
+    }
+
+    /// Block fullness targeted by the mechanism, if any
+    fn target_fullness(&self) -> Option<f64> {
+        None
+    }
+
+    /// Names of the quantities specific to the mechanism, such as the internal state of its
+    /// controller, written as extra columns of `out.csv`
+    fn diagnostic_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Values of the quantities named by `diagnostic_names` after the last price update
+    fn diagnostics(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    /// Gas price burnt by every transaction in the next block
+    fn base_fee(&self) -> u64 {
+        0
+    }
+
+    /// Gas price charged to a transaction at inclusion
+    fn charge(&self, tx: &Transaction) -> u64 {
+        tx.effective_gas_price(self.base_fee())
+    }
+
+    /// Strategy by which block producers select transactions when none is configured
+    fn block_packing(&self) -> PackingStrategy {
+        PackingStrategy::default()
+    }
+}
+
+impl<M: FeeMechanism + ?Sized> FeeMechanism for Box<M> {
+    fn update_price(&mut self, state: &MarketState) {
+        (**self).update_price(state)
+    }
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        (**self).price_tx(wtp, gas_used)
+    }
+
+    fn gas_price(&self) -> u64 {
+        (**self).gas_price()
+    }
+
+    fn block_gas_price(&self, block: &Block) -> u64 {
+        (**self).block_gas_price(block)
+    }
+
+    fn control_fullness(&self) -> f64 {
+        (**self).control_fullness()
+    }
+
+    fn target_fullness(&self) -> Option<f64> {
+        (**self).target_fullness()
+    }
+
+    fn diagnostic_names(&self) -> &'static [&'static str] {
+        (**self).diagnostic_names()
+    }
+
+    fn diagnostics(&self) -> Vec<f64> {
+        (**self).diagnostics()
+    }
+
+    fn base_fee(&self) -> u64 {
+        (**self).base_fee()
+    }
+
+    fn charge(&self, tx: &Transaction) -> u64 {
+        (**self).charge(tx)
+    }
+
+    fn block_packing(&self) -> PackingStrategy {
+        (**self).block_packing()
+    }
+}
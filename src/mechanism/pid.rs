@@ -0,0 +1,170 @@
+use crate::mechanism::autoprice::control_fullness;
+use crate::mechanism::{FeeMechanism, MarketState};
+use crate::transaction::Transaction;
+
+/// Terms of the PID controller, as contributions to the log of the gas price
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PidTerms {
+    pub proportional: f64,
+    pub integral: f64,
+    pub derivative: f64,
+}
+
+/// Fixed gas price set every `control_range` blocks by a PID controller on the fullness error,
+/// i.e. the median fullness of the last `control_range` blocks minus `target_fullness`. The log of
+/// the price moves away from the log of `initial_price` by the sum of the proportional, integral
+/// and derivative terms. The accumulated error is kept within `integral_limit`, so that it does
+/// not wind up while the price cannot clear the blocks.
+///
+/// AutoPrice is the special case of a pure integral controller on the sign of the error.
+pub struct Pid {
+    initial_price: f64,
+    price: u64,
+    control_range: u64,
+    target_fullness: f64,
+    proportional_gain: f64,
+    integral_gain: f64,
+    derivative_gain: f64,
+    integral_limit: f64,
+    control_fullness: f64,
+    accumulated_error: f64,
+    last_error: Option<f64>,
+    terms: PidTerms,
+}
+
+impl Pid {
+    /// Creates the controller. Without an `integral_limit`, the accumulated error is unbounded.
+    pub fn new(
+        initial_price: u64,
+        control_range: u64,
+        target_fullness: f64,
+        proportional_gain: f64,
+        integral_gain: f64,
+        derivative_gain: f64,
+        integral_limit: Option<f64>,
+    ) -> Pid {
+        Pid {
+            initial_price: initial_price as f64,
+            price: initial_price,
+            control_range,
+            target_fullness,
+            proportional_gain,
+            integral_gain,
+            derivative_gain,
+            integral_limit: integral_limit.unwrap_or(f64::INFINITY),
+            control_fullness: 0.,
+            accumulated_error: 0.,
+            last_error: None,
+            terms: PidTerms::default(),
+        }
+    }
+
+    /// Terms of the last adjustment of the price
+    pub fn terms(&self) -> PidTerms {
+        self.terms
+    }
+}
+
+impl FeeMechanism for Pid {
+    fn update_price(&mut self, state: &MarketState) {
+        if let Some(control_fullness) = control_fullness(state, self.control_range) {
+            self.control_fullness = control_fullness;
+            let error = control_fullness - self.target_fullness;
+
+            self.accumulated_error = (self.accumulated_error + error)
+                .max(-self.integral_limit)
+                .min(self.integral_limit);
+            // No derivative kick at the first adjustment
+            let change = error - self.last_error.unwrap_or(error);
+            self.last_error = Some(error);
+
+            self.terms = PidTerms {
+                proportional: self.proportional_gain * error,
+                integral: self.integral_gain * self.accumulated_error,
+                derivative: self.derivative_gain * change,
+            };
+            let output = self.terms.proportional + self.terms.integral + self.terms.derivative;
+            self.price = (self.initial_price * output.exp()) as u64;
+        }
+    }
+
+    fn price_tx(&self, wtp: f64, gas_used: u64) -> Option<Transaction> {
+        if wtp >= self.price as f64 {
+            Some(Transaction::new(gas_used, self.price))
+        } else {
+            None
+        }
+    }
+
+    fn gas_price(&self) -> u64 {
+        self.price
+    }
+
+    fn control_fullness(&self) -> f64 {
+        self.control_fullness
+    }
+
+    fn target_fullness(&self) -> Option<f64> {
+        Some(self.target_fullness)
+    }
+
+    fn diagnostic_names(&self) -> &'static [&'static str] {
+        &["pid_proportional", "pid_integral", "pid_derivative"]
+    }
+
+    fn diagnostics(&self) -> Vec<f64> {
+        vec![
+            self.terms.proportional,
+            self.terms.integral,
+            self.terms.derivative,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pid;
+    use crate::block::Block;
+    use crate::mechanism::{FeeMechanism, MarketState};
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_terms() {
+        let mut mechanism = Pid::new(1000, 1, 0.5, 1., 0.5, 2., Some(0.6));
+        let block = |gas_used: u64| {
+            let mut block = Block::new(10);
+            block.add_txs(vec![Transaction::new(gas_used, 1)]);
+            block
+        };
+        let blocks = [block(10), block(10), block(10), block(5)];
+        let mut update = |height: u64, expected: [f64; 3]| {
+            mechanism.update_price(&MarketState {
+                height,
+                time: height * 600,
+                token_price: 1.,
+                blocks: &blocks[..height as usize],
+            });
+            let terms = mechanism.terms();
+            let terms = [terms.proportional, terms.integral, terms.derivative];
+            for (term, expected) in terms.iter().zip(expected.iter()) {
+                assert!(
+                    (term - expected).abs() < 1e-9,
+                    "{:?} != {:?}",
+                    terms,
+                    expected
+                );
+            }
+            mechanism.gas_price()
+        };
+
+        // Full blocks: an error of 0.5, without derivative at the first adjustment
+        update(1, [0.5, 0.25, 0.]);
+        // The accumulated error is held at the limit
+        update(2, [0.5, 0.3, 0.]);
+        update(3, [0.5, 0.3, 0.]);
+        // Blocks on target: the error drops by 0.5
+        let price = update(4, [0., 0.3, -1.]);
+        // 1000 * exp(-0.7) = 496.6
+        assert!((496..=497).contains(&price));
+    }
+}
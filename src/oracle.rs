@@ -0,0 +1,118 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+use stats::median;
+use std::collections::VecDeque;
+
+/// On-chain price oracle, through which fee mechanisms see the token price. Every
+/// `update_interval` seconds, each reporter observes the token price with a lognormal relative
+/// error of standard deviation `noise`, and the median of the reports is published `delay`
+/// seconds later. Before the first publication, the oracle gives the token price at the start.
+pub struct PriceOracle {
+    update_interval: u64,
+    delay: u64,
+    noise: f64,
+    n_reporters: u64,
+    /// Time of the next observation [second]
+    next_observation: u64,
+    /// Observations waiting for their publication, with their publication time
+    pending: VecDeque<(u64, f64)>,
+    published: Option<f64>,
+}
+
+impl PriceOracle {
+    /// Creates an oracle observing the price every `update_interval` seconds, or every block if 0
+    pub fn new(update_interval: u64, delay: u64, noise: f64, n_reporters: u64) -> PriceOracle {
+        assert!(noise >= 0., "Oracle noise must not be negative");
+        assert!(n_reporters > 0, "Oracle needs at least one reporter");
+        PriceOracle {
+            update_interval,
+            delay,
+            noise,
+            n_reporters,
+            next_observation: 0,
+            pending: VecDeque::new(),
+            published: None,
+        }
+    }
+
+    /// Returns the price published by the oracle at `time`, given the true token price at any
+    /// time up to `time`
+    pub fn update<R, F>(&mut self, rng: &mut R, time: u64, token_price: F) -> f64
+    where
+        R: Rng + ?Sized,
+        F: Fn(u64) -> f64,
+    {
+        if self.update_interval == 0 {
+            self.next_observation = time;
+        }
+        while self.next_observation <= time {
+            let observation = self.next_observation;
+            let report = self.report(rng, token_price(observation));
+            self.pending.push_back((observation + self.delay, report));
+            self.next_observation = observation + self.update_interval.max(1);
+        }
+
+        while let Some(&(publication, report)) = self.pending.front() {
+            if publication > time {
+                break;
+            }
+            self.published = Some(report);
+            self.pending.pop_front();
+        }
+
+        self.published.unwrap_or_else(|| token_price(0))
+    }
+
+    /// Median of the prices reported by the reporters
+    fn report<R: Rng + ?Sized>(&self, rng: &mut R, price: f64) -> f64 {
+        if self.noise == 0. {
+            return price;
+        }
+        let reports = (0..self.n_reporters).map(|_| {
+            let z: f64 = rng.sample(StandardNormal);
+            price * (self.noise * z).exp()
+        });
+        median(reports).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PriceOracle;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_lag() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let token_price = |time: u64| 1. + time as f64;
+
+        // Observed every 1200 seconds, and published 600 seconds later
+        let mut oracle = PriceOracle::new(1200, 600, 0., 1);
+        let published: Vec<f64> = (0..6)
+            .map(|block| oracle.update(&mut rng, block * 600, token_price))
+            .collect();
+        assert_eq!(published, vec![1., 1., 1., 1201., 1201., 2401.]);
+
+        // Updated every block, without delay
+        let mut oracle = PriceOracle::new(0, 0, 0., 1);
+        assert_eq!(oracle.update(&mut rng, 600, token_price), 601.);
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spread = |n_reporters: u64, rng: &mut StdRng| {
+            let mut oracle = PriceOracle::new(0, 0, 0.1, n_reporters);
+            let errors: Vec<f64> = (0..1000)
+                .map(|block| oracle.update(rng, block, |_| 100.) / 100. - 1.)
+                .collect();
+            stats::stddev(errors.into_iter())
+        };
+
+        let single = spread(1, &mut rng);
+        assert!((single - 0.1).abs() < 0.01);
+        // The median of several reporters is less noisy
+        assert!(spread(5, &mut rng) < 0.8 * single);
+    }
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::transaction::Transaction;
+
+/// Number of gas units the block is divided into by the knapsack strategy
+const KNAPSACK_RESOLUTION: u64 = 1_000;
+
+/// Strategy by which the block producer selects transactions from the pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackingStrategy {
+    /// Include transactions by decreasing priority, stopping at the first one that doesn't fit
+    #[default]
+    StopAtFirstMisfit,
+    /// Include transactions by decreasing priority, skipping the ones that don't fit
+    GreedySkip,
+    /// Include transactions by decreasing priority fee per gas under the current base fee,
+    /// skipping the ones that don't fit
+    FeeDensity,
+    /// Include the transactions maximizing the total priority fee received by the block producer,
+    /// solved approximately by dividing the block into `KNAPSACK_RESOLUTION` units of gas
+    Knapsack,
+}
+
+/// Selects the transactions of each block following a packing strategy, keeping the buffers of
+/// the knapsack strategy from one block to the next
+#[derive(Debug, Default)]
+pub struct BlockPacker {
+    strategy: PackingStrategy,
+    // best[c]: highest total fee with at most c units of gas, over the items considered so far
+    best: Vec<u128>,
+    // taken[k * (capacity + 1) + c]: whether the kth item is part of best[c] once considered
+    taken: Vec<bool>,
+}
+
+impl BlockPacker {
+    pub fn new(strategy: PackingStrategy) -> BlockPacker {
+        BlockPacker {
+            strategy,
+            best: Vec::new(),
+            taken: Vec::new(),
+        }
+    }
+
+    /// Selects transactions to fill a block of `gas_limit` under `base_fee`, out of the candidates
+    /// given from the highest to the lowest priority. Candidates are read as needed, until less
+    /// than `min_gas_used`, the lowest gas used by any candidate, is left in the block. Returns
+    /// the selected candidates, in their order of inclusion.
+    pub fn select<'a>(
+        &mut self,
+        candidates: impl Iterator<Item = &'a Transaction>,
+        gas_limit: u64,
+        base_fee: u64,
+        min_gas_used: u64,
+    ) -> Vec<&'a Transaction> {
+        let includable = candidates.filter(move |tx| tx.is_includable(base_fee));
+
+        match self.strategy {
+            PackingStrategy::StopAtFirstMisfit => {
+                let mut gas_left = gas_limit;
+                includable
+                    .take_while(|tx| {
+                        let fits = tx.gas_used() <= gas_left;
+                        if fits {
+                            gas_left -= tx.gas_used();
+                        }
+                        fits
+                    })
+                    .collect()
+            }
+            PackingStrategy::GreedySkip => fill(includable, gas_limit, min_gas_used),
+            PackingStrategy::FeeDensity => fill(
+                by_fee_density(includable, base_fee),
+                gas_limit,
+                min_gas_used,
+            ),
+            PackingStrategy::Knapsack => self.knapsack(
+                by_fee_density(includable, base_fee),
+                gas_limit,
+                base_fee,
+                min_gas_used,
+            ),
+        }
+    }
+
+    /// Solves the knapsack problem over the densest candidates worth twice the block, with the
+    /// gas used rounded up to units of `gas_limit / KNAPSACK_RESOLUTION`. The space lost to
+    /// rounding is then filled by decreasing fee density.
+    fn knapsack<'a>(
+        &mut self,
+        mut by_density: impl Iterator<Item = &'a Transaction>,
+        gas_limit: u64,
+        base_fee: u64,
+        min_gas_used: u64,
+    ) -> Vec<&'a Transaction> {
+        let mut items: Vec<&Transaction> = Vec::new();
+        let mut gas_total = 0;
+        while gas_total < 2 * gas_limit {
+            match by_density.next() {
+                Some(tx) => {
+                    gas_total += tx.gas_used();
+                    items.push(tx);
+                }
+                None => break,
+            }
+        }
+
+        let unit = gas_limit.div_ceil(KNAPSACK_RESOLUTION).max(1);
+        let capacity = (gas_limit / unit) as usize;
+        let width = capacity + 1;
+
+        self.best.clear();
+        self.best.resize(width, 0);
+        self.taken.clear();
+        self.taken.resize(items.len() * width, false);
+        for (k, tx) in items.iter().enumerate() {
+            let weight = tx.gas_used().div_ceil(unit) as usize;
+            let value = tx.priority_fee(base_fee) as u128 * tx.gas_used() as u128;
+
+            if weight <= capacity {
+                let taken = &mut self.taken[k * width..(k + 1) * width];
+                for c in (weight..=capacity).rev() {
+                    let with_item = self.best[c - weight] + value;
+                    if with_item > self.best[c] {
+                        self.best[c] = with_item;
+                        taken[c] = true;
+                    }
+                }
+            }
+        }
+
+        let mut is_selected = vec![false; items.len()];
+        let mut c = capacity;
+        for (k, tx) in items.iter().enumerate().rev() {
+            if self.taken[k * width + c] {
+                is_selected[k] = true;
+                c -= tx.gas_used().div_ceil(unit) as usize;
+            }
+        }
+
+        let mut selected: Vec<&Transaction> = items
+            .iter()
+            .zip(is_selected.iter())
+            .filter(|(_, &s)| s)
+            .map(|(&tx, _)| tx)
+            .collect();
+
+        let gas_used: u64 = selected.iter().map(|tx| tx.gas_used()).sum();
+        let rest = items
+            .iter()
+            .zip(is_selected.iter())
+            .filter(|(_, &s)| !s)
+            .map(|(&tx, _)| tx)
+            .chain(by_density);
+        selected.extend(fill(rest, gas_limit - gas_used, min_gas_used));
+        selected
+    }
+}
+
+/// Orders candidates given by decreasing priority by decreasing priority fee per gas, keeping the
+/// priority order between equals. As the priority fee of a transaction is at most its priority, a
+/// candidate is returned as soon as no candidate left can pay a higher priority fee.
+fn by_fee_density<'a>(
+    candidates: impl Iterator<Item = &'a Transaction>,
+    base_fee: u64,
+) -> impl Iterator<Item = &'a Transaction> {
+    let mut candidates = candidates.peekable();
+    let mut seen: Vec<&Transaction> = Vec::new();
+    let mut heap: BinaryHeap<(u64, Reverse<usize>)> = BinaryHeap::new();
+
+    std::iter::from_fn(move || {
+        loop {
+            let ready = match (heap.peek(), candidates.peek()) {
+                (Some(&(fee, _)), Some(next)) => fee >= next.priority(),
+                (_, Some(_)) => false,
+                (Some(_), None) => true,
+                (None, None) => return None,
+            };
+            if ready {
+                break;
+            }
+            let tx = candidates.next().unwrap();
+            heap.push((tx.priority_fee(base_fee), Reverse(seen.len())));
+            seen.push(tx);
+        }
+        heap.pop().map(|(_, Reverse(k))| seen[k])
+    })
+}
+
+/// Includes the candidates in the given order, skipping the ones that don't fit, until less than
+/// `min_gas_used` is left in the block
+fn fill<'a>(
+    mut candidates: impl Iterator<Item = &'a Transaction>,
+    gas_limit: u64,
+    min_gas_used: u64,
+) -> Vec<&'a Transaction> {
+    let mut gas_left = gas_limit;
+    let mut selected = Vec::new();
+    while gas_left > 0 && gas_left >= min_gas_used {
+        match candidates.next() {
+            Some(tx) if tx.gas_used() <= gas_left => {
+                gas_left -= tx.gas_used();
+                selected.push(tx);
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockPacker, PackingStrategy};
+    use crate::transaction::Transaction;
+
+    /// Packs the candidates, given from the highest to the lowest priority, and returns the
+    /// indices of the selected ones
+    fn select(
+        strategy: PackingStrategy,
+        txs: &[Transaction],
+        gas_limit: u64,
+        base_fee: u64,
+    ) -> Vec<usize> {
+        BlockPacker::new(strategy)
+            .select(txs.iter(), gas_limit, base_fee, 0)
+            .into_iter()
+            .map(|selected| {
+                txs.iter()
+                    .position(|tx| std::ptr::eq(tx, selected))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn fee(txs: &[Transaction], selected: &[usize]) -> u64 {
+        selected.iter().map(|&i| txs[i].fee()).sum()
+    }
+
+    #[test]
+    fn test_strategies() {
+        // From the highest to the lowest priority
+        let txs = [
+            Transaction::new(60, 10),
+            Transaction::new(50, 9),
+            Transaction::new(50, 9),
+            Transaction::new(30, 8),
+        ];
+
+        let first_misfit = select(PackingStrategy::StopAtFirstMisfit, &txs, 100, 0);
+        assert_eq!(first_misfit, vec![0]);
+
+        let greedy_skip = select(PackingStrategy::GreedySkip, &txs, 100, 0);
+        assert_eq!(greedy_skip, vec![0, 3]);
+        assert_eq!(fee(&txs, &greedy_skip), 840);
+
+        let knapsack = select(PackingStrategy::Knapsack, &txs, 100, 0);
+        assert_eq!(fee(&txs, &knapsack), 900);
+    }
+
+    #[test]
+    fn test_fee_density() {
+        // Priority fees of 10, none and 20 under a base fee of 60
+        let txs = [
+            Transaction::new_eip1559(10, 70, 50),
+            Transaction::new_eip1559(10, 30, 30),
+            Transaction::new_eip1559(10, 200, 20),
+        ];
+
+        assert_eq!(select(PackingStrategy::GreedySkip, &txs, 10, 60), vec![0]);
+        assert_eq!(select(PackingStrategy::FeeDensity, &txs, 10, 60), vec![2]);
+        assert_eq!(
+            select(PackingStrategy::FeeDensity, &txs, 20, 60),
+            vec![2, 0]
+        );
+    }
+
+    #[test]
+    fn test_lazy_selection() {
+        let txs: Vec<Transaction> = (0..10)
+            .rev()
+            .map(|price| Transaction::new(10, price))
+            .collect();
+        let mut n_read = 0;
+        let candidates = txs.iter().inspect(|_| n_read += 1);
+
+        // The block is full after 3 candidates, as no transaction uses less than 10 gas
+        let selected = BlockPacker::new(PackingStrategy::GreedySkip).select(candidates, 35, 0, 10);
+        assert_eq!(selected.len(), 3);
+        assert_eq!(n_read, 3);
+    }
+
+    #[test]
+    fn test_knapsack_reuses_buffers() {
+        let txs = [
+            Transaction::new(60, 10),
+            Transaction::new(50, 9),
+            Transaction::new(50, 9),
+            Transaction::new(30, 8),
+        ];
+        let mut packer = BlockPacker::new(PackingStrategy::Knapsack);
+        let fees: Vec<u64> = [100, 100, 60]
+            .iter()
+            .map(|&gas_limit| {
+                packer
+                    .select(txs.iter(), gas_limit, 0, 30)
+                    .iter()
+                    .map(|tx| tx.fee())
+                    .sum()
+            })
+            .collect();
+        assert_eq!(fees, vec![900, 900, 600]);
+    }
+}
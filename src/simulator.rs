@@ -1,31 +1,139 @@
 use indicatif::ProgressBar;
-use stats::median;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
-use std::iter;
 
+use crate::arrivals::Arrivals;
+use crate::backlog::{LatentBacklog, WaitingUser};
+use crate::bands::{write_bands, BlockSeries};
 use crate::block::Block;
-use crate::demand::DemandCurve;
+use crate::config::SimulatorConfig;
+use crate::demand::{DemandCurve, KeyframedDemandCurve};
+use crate::gas::GasUsage;
 use crate::helper::LinearInterpolator;
-use crate::transaction::{Transaction, TransactionPool};
-
-pub struct FeeMarketSimulator {
-    demand_curve: DemandCurve,
+use crate::mechanism::{AutoPrice, FeeMechanism, MarketState};
+use crate::oracle::PriceOracle;
+use crate::packing::{BlockPacker, PackingStrategy};
+use crate::summary::{RunStats, Summary};
+use crate::token_price::{TimeAlignment, TokenPriceGenerator};
+use crate::transaction::{EvictionPolicy, Transaction, TransactionPool, Ttl};
+use crate::tx_log::{TxLog, TxLogFormat, TxOutcome};
+use crate::user_class::UserClass;
+
+pub struct FeeMarketSimulator<M: FeeMechanism = Box<dyn FeeMechanism>> {
+    user_classes: Vec<UserClass>,
+    arrivals: Arrivals,
     token_price: Option<LinearInterpolator>,
-    initial_price: u64,
+    token_price_generator: Option<TokenPriceGenerator>,
+    token_price_alignment: TimeAlignment,
+    oracle: Option<PriceOracle>,
+    mechanism: M,
     block_gas_limit: u64,
-    tx_gas_used: u64,
     block_time: u64,
-    control_range: u64,
-    target_fullness: f64,
-    price_adjustment_rate: f64,
     txpool: TransactionPool,
+    block_packer: BlockPacker,
+    tx_log_format: Option<TxLogFormat>,
     blocks: Vec<Block>,
+    seed: u64,
+    rng: StdRng,
+    show_progress: bool,
+    run_stats: RunStats,
+    latent_backlog: LatentBacklog,
 }
 
 impl FeeMarketSimulator {
+    /// Creates a simulator as described by a config, reading its input files
+    pub fn from_config(config: &SimulatorConfig) -> FeeMarketSimulator {
+        let token_price = config
+            .token_price_path
+            .as_ref()
+            .map(|path| LinearInterpolator::from_csv(path).unwrap_or_else(|e| panic!("{}", e)));
+
+        let mut sim = FeeMarketSimulator::from_user_classes(
+            config.user_classes(),
+            token_price,
+            config.mechanism.build(),
+            config.block_gas_limit,
+            config.txpool_size,
+            config.block_time,
+        )
+        .with_eviction_policy(config.txpool_eviction_policy)
+        .with_arrivals(config.arrivals)
+        .with_token_price_alignment(config.token_price_alignment());
+
+        if let Some(block_packing) = config.block_packing {
+            sim = sim.with_block_packing(block_packing);
+        }
+        if let Some(token_price) = &config.token_price {
+            sim = sim.with_token_price_generator(
+                token_price.build().unwrap_or_else(|e| panic!("{}", e)),
+            );
+        }
+        if let Some(oracle) = &config.oracle {
+            sim = sim.with_oracle(oracle.build());
+        }
+        if let Some(tx_log) = config.tx_log {
+            sim = sim.with_tx_log(tx_log);
+        }
+        if let Some(seed) = config.seed {
+            sim = sim.with_seed(seed);
+        }
+
+        sim
+    }
+
+    /// Runs the simulation described by a config, writing the outputs to its output directory.
+    /// With more than one replica, each replica is written to its own subdirectory and the
+    /// per-block outputs are aggregated into `out_bands.csv`.
+    pub fn run_config(config: &SimulatorConfig) -> Vec<Summary> {
+        let demand_profiles = config.demand_profiles();
+
+        if config.replicas <= 1 {
+            let summary = FeeMarketSimulator::from_config(config)
+                .run_classes(demand_profiles, config.output_dir.clone());
+            return vec![summary];
+        }
+
+        let seed = config.seed.unwrap_or_else(random_seed);
+        fs::create_dir_all(&config.output_dir).expect("Could not create the output directory");
+        fs::write(config.output_dir.join("seed.txt"), format!("{}\n", seed))
+            .expect("Could not write the seed");
+
+        let bar = ProgressBar::new(config.replicas);
+
+        let (summaries, series): (Vec<Summary>, Vec<BlockSeries>) = (0..config.replicas)
+            .into_par_iter()
+            .map(|i| {
+                let mut sim = FeeMarketSimulator::from_config(config)
+                    .with_seed(derive_seed(seed, i))
+                    .with_progress_bar(false);
+                let summary = sim.run_classes(
+                    demand_profiles.clone(),
+                    config.output_dir.join(format!("replica_{:04}", i)),
+                );
+                bar.inc(1);
+                (summary, BlockSeries::new(sim.run_stats()))
+            })
+            .unzip();
+
+        bar.finish();
+
+        write_bands(
+            &config.output_dir.join("out_bands.csv"),
+            &series,
+            config.block_time,
+        );
+
+        summaries
+    }
+}
+
+impl FeeMarketSimulator<AutoPrice> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new_autoprice_simulator(
         demand_curve: DemandCurve,
         token_price: Option<LinearInterpolator>,
@@ -37,119 +145,521 @@ impl FeeMarketSimulator {
         control_range: u64,
         target_fullness: f64,
         price_adjustment_rate: f64,
-    ) -> FeeMarketSimulator {
-        FeeMarketSimulator {
+    ) -> FeeMarketSimulator<AutoPrice> {
+        FeeMarketSimulator::new(
             demand_curve,
             token_price,
-            initial_price,
+            AutoPrice::new(
+                initial_price,
+                control_range,
+                target_fullness,
+                price_adjustment_rate,
+            ),
             block_gas_limit,
             tx_gas_used,
+            txpool_size,
+            block_time,
+        )
+    }
+}
+
+impl<M: FeeMechanism> FeeMarketSimulator<M> {
+    pub fn new(
+        demand_curve: impl Into<KeyframedDemandCurve>,
+        token_price: Option<LinearInterpolator>,
+        mechanism: M,
+        block_gas_limit: u64,
+        tx_gas_used: u64,
+        txpool_size: usize,
+        block_time: u64,
+    ) -> FeeMarketSimulator<M> {
+        let user_class = UserClass::new(
+            "default".to_string(),
+            demand_curve,
+            GasUsage::Constant(tx_gas_used),
+        );
+        FeeMarketSimulator::from_user_classes(
+            vec![user_class],
+            token_price,
+            mechanism,
+            block_gas_limit,
+            txpool_size,
+            block_time,
+        )
+    }
+
+    /// Creates a simulator with several classes of users, each with its own demand profile when
+    /// running with `run_classes`
+    pub fn from_user_classes(
+        user_classes: Vec<UserClass>,
+        token_price: Option<LinearInterpolator>,
+        mechanism: M,
+        block_gas_limit: u64,
+        txpool_size: usize,
+        block_time: u64,
+    ) -> FeeMarketSimulator<M> {
+        assert!(!user_classes.is_empty(), "No user classes given");
+        let seed = random_seed();
+        let block_packer = BlockPacker::new(mechanism.block_packing());
+        FeeMarketSimulator {
+            user_classes,
+            arrivals: Arrivals::Fixed,
+            token_price,
+            token_price_generator: None,
+            token_price_alignment: TimeAlignment::default(),
+            oracle: None,
+            mechanism,
+            block_gas_limit,
             block_time,
-            control_range,
-            target_fullness,
-            price_adjustment_rate,
             txpool: TransactionPool::new(txpool_size),
+            block_packer,
+            tx_log_format: None,
             blocks: Vec::new(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            show_progress: true,
+            run_stats: RunStats::new(),
+            latent_backlog: LatentBacklog::new(),
         }
     }
 
-    pub fn run(&mut self, n_user_vec: Vec<u64>, output_dir: PathBuf) {
+    /// Seeds the random number generator, so that runs with the same seed give the same output.
+    /// Otherwise, a random seed is used.
+    pub fn with_seed(mut self, seed: u64) -> FeeMarketSimulator<M> {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Draws the gas used by each transaction of every class of users from a distribution,
+    /// instead of using the same amount for every transaction
+    pub fn with_tx_gas(mut self, tx_gas: GasUsage) -> FeeMarketSimulator<M> {
+        self.user_classes = self
+            .user_classes
+            .into_iter()
+            .map(|user_class| user_class.with_tx_gas(tx_gas.clone()))
+            .collect();
+        self
+    }
+
+    /// Draws the number of users in each block from a distribution around the demand profile,
+    /// instead of taking it as is
+    pub fn with_arrivals(mut self, arrivals: Arrivals) -> FeeMarketSimulator<M> {
+        self.arrivals = arrivals;
+        self
+    }
+
+    /// Generates a synthetic token price path at the start of every run, replacing the token price
+    /// data
+    pub fn with_token_price_generator(
+        mut self,
+        token_price_generator: TokenPriceGenerator,
+    ) -> FeeMarketSimulator<M> {
+        self.token_price_generator = Some(token_price_generator);
+        self
+    }
+
+    /// Sets which part of the token price data the run replays, at which pace, and what happens
+    /// once it runs out of data
+    pub fn with_token_price_alignment(
+        mut self,
+        token_price_alignment: TimeAlignment,
+    ) -> FeeMarketSimulator<M> {
+        self.token_price_alignment = token_price_alignment;
+        self
+    }
+
+    /// Makes the fee mechanism see the token price through an on-chain oracle, instead of the
+    /// exact token price
+    pub fn with_oracle(mut self, oracle: PriceOracle) -> FeeMarketSimulator<M> {
+        self.oracle = Some(oracle);
+        self
+    }
+
+    /// Sets the policy by which transactions are dropped once the transaction pool is full
+    pub fn with_eviction_policy(
+        mut self,
+        eviction_policy: EvictionPolicy,
+    ) -> FeeMarketSimulator<M> {
+        self.txpool = TransactionPool::with_eviction_policy(self.txpool.limit(), eviction_policy);
+        self
+    }
+
+    /// Sets the strategy by which transactions are selected from the pool to fill blocks
+    pub fn with_block_packing(mut self, block_packing: PackingStrategy) -> FeeMarketSimulator<M> {
+        self.block_packer = BlockPacker::new(block_packing);
+        self
+    }
+
+    /// Makes the priced-out users of every class wait up to `patience` blocks for the price to
+    /// drop to their willingness to pay, and resubmit then
+    pub fn with_patience(mut self, patience: u64) -> FeeMarketSimulator<M> {
+        self.user_classes = self
+            .user_classes
+            .into_iter()
+            .map(|user_class| user_class.with_patience(patience))
+            .collect();
+        self
+    }
+
+    /// Sets the time-to-live of the transactions of every class of users, after which they are
+    /// dropped from the pool
+    pub fn with_tx_ttl(mut self, tx_ttl: Ttl) -> FeeMarketSimulator<M> {
+        self.user_classes = self
+            .user_classes
+            .into_iter()
+            .map(|user_class| user_class.with_tx_ttl(tx_ttl))
+            .collect();
+        self
+    }
+
+    /// Enables the per-transaction output log, written next to `out.csv`
+    pub fn with_tx_log(mut self, tx_log_format: TxLogFormat) -> FeeMarketSimulator<M> {
+        self.tx_log_format = Some(tx_log_format);
+        self
+    }
+
+    /// Shows or hides the progress bar while running
+    pub fn with_progress_bar(mut self, show_progress: bool) -> FeeMarketSimulator<M> {
+        self.show_progress = show_progress;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn user_classes(&self) -> &[UserClass] {
+        &self.user_classes
+    }
+
+    /// Blocks produced by the last run
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Per-block quantities collected during the last run
+    pub fn run_stats(&self) -> &RunStats {
+        &self.run_stats
+    }
+
+    /// Runs the simulation for the given number of users in each block, writes the outputs to
+    /// `output_dir` and returns the summary of the run. The users all belong to the first class.
+    pub fn run(&mut self, n_user_vec: Vec<u64>, output_dir: PathBuf) -> Summary {
+        let mut demand_profiles = vec![Vec::new(); self.user_classes.len()];
+        demand_profiles[0] = n_user_vec;
+        self.run_classes(demand_profiles, output_dir)
+    }
+
+    /// Runs the simulation for the given number of users of each class in each block, writes the
+    /// outputs to `output_dir` and returns the summary of the run. The run lasts as long as the
+    /// longest demand profile, the shorter ones having no users past their end.
+    pub fn run_classes(&mut self, demand_profiles: Vec<Vec<u64>>, output_dir: PathBuf) -> Summary {
+        assert_eq!(
+            demand_profiles.len(),
+            self.user_classes.len(),
+            "Expected one demand profile per user class"
+        );
+        let n_blocks = demand_profiles.iter().map(Vec::len).max().unwrap_or(0);
+
         let mut output_csv_path = output_dir.clone();
         output_csv_path.push("out.csv");
 
-        let _ = fs::create_dir_all(output_dir).expect("Could not create the output directory");
+        let mut seed_path = output_dir.clone();
+        seed_path.push("seed.txt");
+
+        fs::create_dir_all(&output_dir).expect("Could not create the output directory");
+
+        fs::write(seed_path, format!("{}\n", self.seed)).expect("Could not write the seed");
 
         let mut output_csv_file = File::create(output_csv_path).unwrap();
+        let mut classes_csv_file = File::create(output_dir.join("out_classes.csv")).unwrap();
 
-        output_csv_file.write_all("height,time,n_user,n_sent_tx,n_included_tx,n_unincluded_tx,txpool_size,control_fullness,token_price,fixed_gas_price\n".as_bytes()).unwrap();
+        let mut summary_path = output_dir.clone();
+        summary_path.push("summary.json");
 
-        let bar = ProgressBar::new(n_user_vec.len() as u64);
+        self.run_stats = RunStats::new();
+        self.latent_backlog = LatentBacklog::new();
 
-        let mut fixed_gas_price: u64 = self.initial_price;
-        let count = 0;
-        let mut control_fullness: f64 = 0.;
+        // The price path is drawn first, so that it only depends on the seed. It covers the part
+        // of the path read by the run, from the start given by the alignment.
+        if let Some(generator) = &self.token_price_generator {
+            let end = self
+                .token_price_alignment
+                .end(n_blocks as u64 * self.block_time);
+            self.token_price = Some(generator.generate(&mut self.rng, end.max(0.).ceil() as u64));
+        }
 
-        for x_ in 0..n_user_vec.len() {
+        let class_labels: Vec<String> = self
+            .user_classes
+            .iter()
+            .map(|user_class| user_class.label().to_string())
+            .collect();
+        let mut tx_log = self
+            .tx_log_format
+            .map(|format| TxLog::create(&output_dir, format, class_labels.clone()))
+            .map(|tx_log| tx_log.expect("Could not create the transaction log"));
+
+        // Transactions are counted by amount of gas for the categorical gas distributions
+        let mut gas_categories: Vec<u64> = self
+            .user_classes
+            .iter()
+            .flat_map(|user_class| user_class.tx_gas().categories().iter().copied())
+            .collect();
+        gas_categories.sort_unstable();
+        gas_categories.dedup();
+        let gas_category_names: String = ["n_sent_tx", "n_included_tx"]
+            .iter()
+            .flat_map(|name| {
+                gas_categories
+                    .iter()
+                    .map(move |gas_used| format!(",{}_gas_{}", name, gas_used))
+            })
+            .collect();
+
+        let diagnostic_names: String = self
+            .mechanism
+            .diagnostic_names()
+            .iter()
+            .map(|name| format!(",{}", name))
+            .collect();
+        output_csv_file.write_all(format!("height,time,n_user,n_sent_tx,n_included_tx,n_unincluded_tx,txpool_size,control_fullness,token_price,fixed_gas_price,base_fee,burnt,tip,min_price,median_price,max_price,n_evicted_tx,n_expired_tx,arrival_rate,gas_used,min_tx_gas_used,median_tx_gas_used,max_tx_gas_used,n_retried_tx,n_abandoned_user,latent_backlog,oracle_token_price,timestamp{}{}\n", gas_category_names, diagnostic_names).as_bytes()).unwrap();
+        classes_csv_file.write_all(format!("height,time,class,arrival_rate,n_user,n_sent_tx,n_priced_out_user,n_included_tx,n_expired_tx,n_evicted_tx,demand_curve_keyframe,n_retried_tx,n_abandoned_user,latent_backlog{}\n", gas_category_names).as_bytes()).unwrap();
+
+        let bar = if self.show_progress {
+            ProgressBar::new(n_blocks as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+
+        for x_ in 0..n_blocks {
             let x = x_ as u64;
             let time = x * self.block_time;
-            let n_user = n_user_vec[x_];
-
-            if x > 0 && x % self.control_range == 0 {
-                let control_blocks: Vec<&Block> = self
-                    .blocks
-                    .iter()
-                    .rev()
-                    .take(self.control_range as usize)
-                    .collect();
 
-                // let control_gas_used: u64 = control_blocks.iter().map(|&b| b.gas_used()).sum();
-                // let max_gas_used = control_blocks.len() as u64 * self.block_gas_limit;
-                // control_fullness = control_gas_used as f64 / max_gas_used as f64;
+            let arrival_rates: Vec<u64> = demand_profiles
+                .iter()
+                .map(|profile| profile.get(x_).copied().unwrap_or(0))
+                .collect();
 
-                control_fullness = median(control_blocks.into_iter().map(Block::fullness))
-                    .expect("No blocks in the control range");
+            // Willingness to pay and gas used of the users of each class
+            let mut users: Vec<(Vec<u64>, Vec<u64>)> = Vec::with_capacity(self.user_classes.len());
+            let (rng, arrivals) = (&mut self.rng, self.arrivals);
+            for (user_class, &arrival_rate) in self.user_classes.iter().zip(arrival_rates.iter()) {
+                let n_user = arrivals.sample(rng, arrival_rate);
 
-                let increase = control_fullness > self.target_fullness;
+                let wtp_vec = user_class
+                    .demand_curve()
+                    .sample_price(rng, time, n_user as usize);
 
-                if increase {
-                    fixed_gas_price =
-                        (fixed_gas_price as f64 * (1. + self.price_adjustment_rate)) as u64;
-                } else {
-                    fixed_gas_price =
-                        (fixed_gas_price as f64 / (1. + self.price_adjustment_rate)) as u64;
-                }
+                let gas_used_vec: Vec<u64> = (0..n_user)
+                    .map(|_| user_class.tx_gas().sample(rng))
+                    .collect();
 
-                // println!("{} {}", fixed_gas_price, control_fullness);
+                users.push((wtp_vec, gas_used_vec));
             }
-
-            let wtp_vec = self.demand_curve.sample_price(n_user as usize);
-
-            // println!("{} {}", n_user, wtp_vec.len());
+            let n_user: u64 = users.iter().map(|(wtp_vec, _)| wtp_vec.len() as u64).sum();
+            let arrival_rate: u64 = arrival_rates.iter().sum();
 
             let mut current_token_price: f64 = 1.;
-
-            let n_sent_tx: u64 = match &self.token_price {
-                Some(interp) => {
-                    // println!("{}", time);
-                    let relative_time = interp.xmin() + time as f64;
-
-                    if relative_time > interp.xmax() {
-                        println!("Token price data not large enough to cover the whole simulation, exiting...");
+            let mut timestamp = None;
+
+            if let Some(interp) = &self.token_price {
+                match self.token_price_alignment.timestamp(interp, time) {
+                    Some(t) => {
+                        timestamp = Some(t);
+                        // token/fiat * gas/token = gas/fiat
+                        current_token_price = interp.interpolate(t);
+                    }
+                    None => {
+                        self.run_stats.record_stop(x);
                         break;
                     }
-                    // token/fiat * gas/token = gas/fiat
-                    current_token_price = interp.interpolate(relative_time);
+                }
+            }
 
-                    wtp_vec
-                        .iter()
-                        .filter(|&&x| x as f64 >= fixed_gas_price as f64 * current_token_price)
-                        .count() as u64
+            // Fee mechanisms see the token price published by the oracle, if any, while users react
+            // to the true price
+            let oracle_token_price = match self.oracle.as_mut() {
+                Some(oracle) => {
+                    let (interp, alignment) =
+                        (self.token_price.as_ref(), self.token_price_alignment);
+                    oracle.update(&mut self.rng, time, |time| {
+                        interp
+                            .and_then(|interp| {
+                                alignment
+                                    .timestamp(interp, time)
+                                    .map(|t| interp.interpolate(t))
+                            })
+                            .unwrap_or(1.)
+                    })
                 }
-                None => wtp_vec.iter().filter(|&&x| x >= fixed_gas_price).count() as u64,
+                None => current_token_price,
             };
 
-            // let txs = iter::repeat(Transaction::new(self.tx_gas_used, fixed_gas_price))
-            //     .take(n_sent_tx as usize)
-            //     .collect();
+            self.mechanism.update_price(&MarketState {
+                height: x,
+                time,
+                token_price: oracle_token_price,
+                blocks: &self.blocks,
+            });
+
+            let (mechanism, user_classes) = (&self.mechanism, &self.user_classes);
+            let price_tx = |class: usize, wtp: u64, gas_used: u64| {
+                let wtp = wtp as f64 / current_token_price;
+                let tx = mechanism
+                    .price_tx(wtp, gas_used)?
+                    .submitted(wtp, x)
+                    .with_class(class);
+                Some(match user_classes[class].tx_ttl() {
+                    Some(ttl) => tx.with_expiry(ttl.expiry(x, time)),
+                    None => tx,
+                })
+            };
 
-            let txs = (0..n_sent_tx).map(|x| Transaction::new(self.tx_gas_used, fixed_gas_price)).collect();
+            // Users priced out in earlier blocks resubmit first, if the price dropped enough
+            let abandoned_users = self.latent_backlog.remove_abandoned(x);
+            let mut txs = self
+                .latent_backlog
+                .retry(|user| price_tx(user.class, user.wtp, user.gas_used));
+            let n_retried_tx = txs.len() as u64;
+            let mut n_retried_class_tx = vec![0; user_classes.len()];
+            for tx in txs.iter() {
+                n_retried_class_tx[tx.class()] += 1;
+            }
 
-            self.txpool.add_txs(txs);
+            let mut n_sent_class_tx = n_retried_class_tx.clone();
+            let mut n_priced_out_class_user = vec![0; user_classes.len()];
+            for (class, (wtp_vec, gas_used_vec)) in users.iter().enumerate() {
+                let patience = user_classes[class].patience().filter(|&p| p > 0);
+                for (&wtp, &gas_used) in wtp_vec.iter().zip(gas_used_vec.iter()) {
+                    match price_tx(class, wtp, gas_used) {
+                        Some(tx) => {
+                            txs.push(tx);
+                            n_sent_class_tx[class] += 1;
+                        }
+                        None => {
+                            n_priced_out_class_user[class] += 1;
+                            if let Some(patience) = patience {
+                                self.latent_backlog.add(WaitingUser {
+                                    class,
+                                    wtp,
+                                    gas_used,
+                                    deadline: x + patience,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
 
-            let included_txs = self.txpool.pop_most_valuable_txs(self.block_gas_limit);
+            let n_sent_tx = txs.len() as u64;
+            let n_sent_gas_tx = count_gas_categories(&txs, &gas_categories, user_classes.len());
+
+            let expired_txs = self.txpool.remove_expired_txs(x, time);
+            let evicted_txs = self.txpool.add_txs(txs);
+            let n_expired_tx = expired_txs.len() as u64;
+            let n_evicted_tx = evicted_txs.len() as u64;
+
+            let base_fee = self.mechanism.base_fee();
+            let mut included_txs =
+                self.txpool
+                    .pop_block_txs(&mut self.block_packer, self.block_gas_limit, base_fee);
+            for tx in included_txs.iter_mut() {
+                let price = self.mechanism.charge(tx);
+                tx.include(x, price);
+            }
+            let n_included_gas_tx =
+                count_gas_categories(&included_txs, &gas_categories, self.user_classes.len());
+
+            if let Some(tx_log) = tx_log.as_mut() {
+                tx_log
+                    .write_txs(&expired_txs, TxOutcome::Expired, Some(x))
+                    .and_then(|_| tx_log.write_txs(&evicted_txs, TxOutcome::Evicted, Some(x)))
+                    .and_then(|_| tx_log.write_txs(&included_txs, TxOutcome::Included, Some(x)))
+                    .expect("Could not write the transaction log");
+            }
 
-            let mut new_block = Block::new(self.block_gas_limit);
+            for (class, user_class) in self.user_classes.iter().enumerate() {
+                let count = |txs: &[Transaction]| {
+                    txs.iter().filter(|tx| tx.class() == class).count() as u64
+                };
+                let n_class_user = users[class].0.len() as u64;
+                let n_class_expired_tx = count(&expired_txs);
+                let n_class_evicted_tx = count(&evicted_txs);
+                let n_class_abandoned_user =
+                    abandoned_users.iter().filter(|u| u.class == class).count() as u64;
+                let class_latent_backlog = self
+                    .latent_backlog
+                    .users()
+                    .iter()
+                    .filter(|u| u.class == class)
+                    .count();
+
+                classes_csv_file
+                    .write_all(
+                        format!(
+                            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}{}\n",
+                            x,
+                            time,
+                            user_class.label(),
+                            arrival_rates[class],
+                            n_class_user,
+                            n_sent_class_tx[class],
+                            n_priced_out_class_user[class], // arriving users priced out by the gas price
+                            count(&included_txs),
+                            n_class_expired_tx,
+                            n_class_evicted_tx,
+                            user_class.demand_curve().keyframe(time), // position between the demand curve keyframes
+                            n_retried_class_tx[class], // transactions resubmitted by priced-out users
+                            n_class_abandoned_user,    // priced-out users who gave up waiting
+                            class_latent_backlog, // priced-out users waiting for the price to drop
+                            format_counts(
+                                n_sent_gas_tx[class].iter().chain(&n_included_gas_tx[class])
+                            )
+                        )
+                        .as_bytes(),
+                    )
+                    .unwrap();
+
+                self.run_stats.record_class(
+                    class,
+                    n_class_user,
+                    n_sent_class_tx[class],
+                    n_priced_out_class_user[class],
+                    n_class_expired_tx,
+                    n_class_evicted_tx,
+                );
+                self.run_stats.record_retries(
+                    class,
+                    n_retried_class_tx[class],
+                    n_class_abandoned_user,
+                );
+            }
+            self.run_stats
+                .record_latent_backlog(self.latent_backlog.size());
+
+            let mut new_block = Block::with_base_fee(self.block_gas_limit, base_fee);
             new_block.add_txs(included_txs);
 
             let n_included_tx = new_block.tx_count();
+            let gas_price = self.mechanism.block_gas_price(&new_block);
             let n_unincluded_tx = n_sent_tx.saturating_sub(n_included_tx);
 
-            self.blocks.push(new_block);
-
+            let gas_category_counts = format_counts(
+                sum_classes(&n_sent_gas_tx)
+                    .iter()
+                    .chain(&sum_classes(&n_included_gas_tx)),
+            );
+            let diagnostics: String = self
+                .mechanism
+                .diagnostics()
+                .iter()
+                .map(|value| format!(",{}", value))
+                .collect();
             output_csv_file
                 .write_all(
                     format!(
-                        "{},{},{},{},{},{},{},{},{},{}\n",
+                        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}{}{}\n",
                         x,
                         x * self.block_time,
                         n_user,             // number of users in the market
@@ -157,17 +667,219 @@ impl FeeMarketSimulator {
                         n_included_tx,      // number of transactions included in the block
                         n_unincluded_tx, // number of transactions sent but not included in the block
                         self.txpool.size(), // size of the transaction pool
-                        control_fullness,
+                        self.mechanism.control_fullness(),
                         current_token_price,
-                        fixed_gas_price
+                        gas_price,
+                        new_block.base_fee(), // base fee burnt per unit of gas
+                        new_block.burnt(),    // total fees burnt in the block
+                        new_block.tips(),     // total fees received by the block producer
+                        format_option(new_block.min_price()), // prices paid in the block
+                        format_option(new_block.median_price()),
+                        format_option(new_block.max_price()),
+                        n_evicted_tx, // transactions evicted or rejected by the full transaction pool
+                        n_expired_tx, // transactions dropped from the pool after their time-to-live
+                        arrival_rate, // number of users given by the demand profiles
+                        new_block.gas_used(), // gas used by the transactions included in the block
+                        format_option(new_block.min_tx_gas_used()),
+                        format_option(new_block.median_tx_gas_used()),
+                        format_option(new_block.max_tx_gas_used()),
+                        n_retried_tx, // transactions resubmitted by priced-out users
+                        abandoned_users.len(), // priced-out users who gave up waiting
+                        self.latent_backlog.size(), // priced-out users waiting for the price to drop
+                        oracle_token_price, // token price seen by the fee mechanism
+                        format_option(timestamp), // timestamp of the token price data
+                        gas_category_counts, // transactions using each categorical amount of gas
+                        diagnostics // quantities specific to the fee mechanism
                     )
                     .as_bytes(),
                 )
                 .unwrap();
 
+            self.run_stats.record_block(
+                n_user,
+                n_sent_tx,
+                n_expired_tx,
+                n_evicted_tx,
+                self.txpool.size(),
+                gas_price,
+                &new_block,
+            );
+
+            // Fee mechanisms only look at the gas used by past blocks
+            new_block.drop_txs();
+            self.blocks.push(new_block);
+
             bar.inc(1);
         }
 
+        if let Some(mut tx_log) = tx_log {
+            self.txpool
+                .txs()
+                .try_for_each(|tx| tx_log.write_tx(tx, TxOutcome::Pending, None))
+                .and_then(|_| tx_log.finish())
+                .expect("Could not write the transaction log");
+        }
+
+        let class_labels: Vec<&str> = class_labels.iter().map(String::as_str).collect();
+        let summary = Summary::new(
+            &self.run_stats,
+            self.txpool.size(),
+            self.latent_backlog.size(),
+            self.mechanism.target_fullness(),
+            self.block_time,
+            self.seed,
+            &class_labels,
+        );
+        summary.write(&summary_path);
+
         bar.finish();
+
+        summary
+    }
+}
+
+/// Draws a random seed that fits in a config file, where integers are signed
+pub fn random_seed() -> u64 {
+    rand::random::<u64>() >> 1
+}
+
+/// Derives the seed of the `index`th replica from the seed of a simulation, with the SplitMix64
+/// mixing function so that consecutive indices give unrelated seeds
+pub fn derive_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add((index + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) >> 1
+}
+
+/// Formats an optional value for CSV output, leaving the field empty if there is no value
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    value.map(|x| x.to_string()).unwrap_or_default()
+}
+
+/// Formats counts as extra CSV columns, each preceded by a comma
+fn format_counts<'a>(counts: impl Iterator<Item = &'a u64>) -> String {
+    counts.map(|count| format!(",{}", count)).collect()
+}
+
+/// Counts the transactions of each class using each of the amounts of gas in `categories`, which
+/// are sorted
+fn count_gas_categories(txs: &[Transaction], categories: &[u64], n_class: usize) -> Vec<Vec<u64>> {
+    let mut counts = vec![vec![0; categories.len()]; n_class];
+    for tx in txs {
+        if let Ok(i) = categories.binary_search(&tx.gas_used()) {
+            counts[tx.class()][i] += 1;
+        }
+    }
+    counts
+}
+
+/// Sums counts made for each class over the classes
+fn sum_classes(counts: &[Vec<u64>]) -> Vec<u64> {
+    let mut total = vec![0; counts.first().map_or(0, Vec::len)];
+    for class_counts in counts {
+        for (sum, count) in total.iter_mut().zip(class_counts) {
+            *sum += count;
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeeMarketSimulator;
+    use crate::config::SimulatorConfig;
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_gas_category_counts() {
+        let output_dir =
+            std::env::temp_dir().join(format!("gas_categories_{}", std::process::id()));
+        let user_class = |label: &str, tx_gas: serde_json::Value| {
+            let mut user_class = json!({
+                "label": label,
+                "demand_curve": {"shape": "linear", "min_price": 0, "max_price": 100},
+                "demand_profile": {"n_blocks": 5, "components": [{"type": "constant", "value": 20}]},
+            });
+            user_class
+                .as_object_mut()
+                .unwrap()
+                .extend(tx_gas.as_object().unwrap().clone());
+            user_class
+        };
+        let raw = json!({
+            "output_dir": output_dir,
+            "block_gas_limit": 300_000,
+            "block_time": 600,
+            "mechanism": "first_price",
+            "min_gas_price": 1,
+            "seed": 1,
+            "user_classes": [
+                user_class("bulk", json!({"tx_gas": {
+                    "distribution": "categorical",
+                    "gas_used": [100_000, 21_000],
+                    "weights": [1, 1],
+                }})),
+                user_class("transfer", json!({"tx_gas_used": 50_000})),
+            ],
+        });
+        let config = SimulatorConfig::from_value(&raw, Path::new("config.toml")).unwrap();
+        FeeMarketSimulator::from_config(&config)
+            .with_progress_bar(false)
+            .run_classes(config.demand_profiles(), output_dir.clone());
+
+        let read = |file: &str| {
+            let mut reader = csv::Reader::from_path(output_dir.join(file)).unwrap();
+            let header: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+            let rows: Vec<Vec<String>> = reader
+                .records()
+                .map(|record| record.unwrap().iter().map(String::from).collect())
+                .collect();
+            (header, rows)
+        };
+        let (header, rows) = read("out.csv");
+        let (classes_header, classes_rows) = read("out_classes.csv");
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        let columns = [
+            "n_sent_tx_gas_21000",
+            "n_sent_tx_gas_100000",
+            "n_included_tx_gas_21000",
+            "n_included_tx_gas_100000",
+        ];
+        let values = |header: &[String], row: &[String], names: &[&str]| -> Vec<u64> {
+            names
+                .iter()
+                .map(|name| {
+                    let i = header.iter().position(|column| column == name).unwrap();
+                    row[i].parse().unwrap()
+                })
+                .collect()
+        };
+
+        assert_eq!(rows.len(), 5);
+        let mut n_included_bulk_tx = 0;
+        for (i, row) in rows.iter().enumerate() {
+            let class_rows = &classes_rows[2 * i..2 * i + 2];
+            assert_eq!(class_rows[0][2], "bulk");
+
+            // Only the bulk class draws its gas from a categorical distribution
+            let bulk = values(&classes_header, &class_rows[0], &columns);
+            let transfer = values(&classes_header, &class_rows[1], &columns);
+            assert_eq!(transfer, vec![0; 4]);
+            assert_eq!(values(&header, row, &columns), bulk);
+
+            let counts = values(
+                &classes_header,
+                &class_rows[0],
+                &["n_sent_tx", "n_included_tx"],
+            );
+            assert_eq!(bulk[0] + bulk[1], counts[0]);
+            assert_eq!(bulk[2] + bulk[3], counts[1]);
+            n_included_bulk_tx += counts[1];
+        }
+        assert!(n_included_bulk_tx > 0);
     }
 }
@@ -6,7 +6,7 @@
 
 use std::fmt;
 
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 
 use std::iter::FromIterator;
 
@@ -130,7 +130,7 @@ impl<K: Ord, V: PartialEq> SortedList<K, V> {
     }
 
     /// Iterate all stored tuples, keys in order, values in insertion order
-    pub fn iter(&self) -> Tuples<K, V> {
+    pub fn iter(&self) -> Tuples<'_, K, V> {
         Tuples {
             keys: &self.keys,
             values: &self.values,
@@ -140,12 +140,12 @@ impl<K: Ord, V: PartialEq> SortedList<K, V> {
     }
 
     /// Iterate over all keys, can contain duplicates
-    pub fn keys(&self) -> ::std::slice::Iter<K> {
+    pub fn keys(&self) -> ::std::slice::Iter<'_, K> {
         self.keys.iter()
     }
 
     /// Iterate over all values
-    pub fn values(&self) -> ::std::slice::Iter<V> {
+    pub fn values(&self) -> ::std::slice::Iter<'_, V> {
         self.values.iter()
     }
 
@@ -244,6 +244,59 @@ impl<K: Ord, V: PartialEq> SortedList<K, V> {
     pub fn get(&self, index: usize) -> &V {
         &self.values[index]
     }
+
+    /// Removes the elements in a given index range and returns them in order
+    pub fn drain(&mut self, range: Range<usize>) -> Vec<(K, V)> {
+        let keys = self.keys.drain(range.clone());
+        let values = self.values.drain(range);
+        keys.zip(values).collect()
+    }
+
+    /// Retains only the tuples for which `f` returns `true`, preserving the order
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let keep: Vec<bool> = self
+            .keys
+            .iter()
+            .zip(self.values.iter())
+            .map(|(k, v)| f(k, v))
+            .collect();
+
+        let mut idx = 0;
+        self.keys.retain(|_| {
+            idx += 1;
+            keep[idx - 1]
+        });
+        let mut idx = 0;
+        self.values.retain(|_| {
+            idx += 1;
+            keep[idx - 1]
+        });
+    }
+
+    /// Retains only the tuples from index `start` on for which `f` returns `true`, preserving the
+    /// order. The tuples before `start` are kept without being visited.
+    pub fn retain_from<F>(&mut self, start: usize, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let keys = self.keys.split_off(start);
+        let values = self.values.split_off(start);
+        for (key, value) in keys.into_iter().zip(values) {
+            if f(&key, &value) {
+                self.keys.push(key);
+                self.values.push(value);
+            }
+        }
+    }
+}
+
+impl<K: Ord, V: PartialEq> Default for SortedList<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<K: Ord + Clone, V: PartialEq + Clone> Clone for SortedList<K, V> {
@@ -282,7 +335,7 @@ impl<A> ResultExt<A> for Result<A, A> {
 
 impl<K: Ord + PartialEq, V: PartialEq> SortedList<K, V> {
     /// Returns an iterator over the specified range of tuples
-    pub fn range<R>(&self, range: R) -> Tuples<K, V>
+    pub fn range<R>(&self, range: R) -> Tuples<'_, K, V>
     where
         R: RangeBounds<K>,
     {
@@ -373,7 +426,7 @@ impl<K: Clone + Ord, V: PartialEq> Extend<(K, V)> for SortedList<K, V> {
         T: IntoIterator<Item = (K, V)>,
     {
         let mut temp = iter.into_iter().collect::<Vec<_>>();
-        temp.sort_by_key(|&(ref k, _)| k.clone());
+        temp.sort_by_key(|(k, _)| k.clone());
 
         for (k, v) in temp {
             self.insert(k, v);
@@ -465,16 +518,13 @@ impl<'a, K, V> Clone for Tuples<'a, K, V> {
 impl<'a, K: Ord + fmt::Debug, V: PartialEq + fmt::Debug> fmt::Debug for Tuples<'a, K, V> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let remaining = self.size_hint().0;
-        let mut clone = self.clone();
-        let mut idx = 0;
         write!(fmt, "[")?;
-        while let Some(tuple) = clone.next() {
+        for (idx, tuple) in self.clone().enumerate() {
             if idx == remaining - 1 {
                 write!(fmt, "{:?}", tuple)?;
             } else {
                 write!(fmt, "{:?}, ", tuple)?;
             }
-            idx += 1;
         }
         write!(fmt, "]")
     }
@@ -981,10 +1031,28 @@ mod tests {
 
     #[test]
     fn from_iter() {
-        let coll = (0..20)
-            .into_iter()
-            .map(|x| (x, x + 5))
-            .collect::<SortedList<_, _>>();
+        let coll = (0..20).map(|x| (x, x + 5)).collect::<SortedList<_, _>>();
         assert_eq!(coll.len(), 20);
     }
+
+    #[test]
+    fn drain() {
+        let mut list: SortedList<u32, u8> = (0..5).map(|x| (x, x as u8)).collect();
+        assert_eq!(list.drain(0..2), vec![(0, 0), (1, 1)]);
+        assert_eq!(to_vec(list.iter()), vec![(2, 2), (3, 3), (4, 4)]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut list: SortedList<u32, u8> = (0..5).map(|x| (x, x as u8)).collect();
+        list.retain(|k, _| k % 2 == 0);
+        assert_eq!(to_vec(list.iter()), vec![(0, 0), (2, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn retain_from() {
+        let mut list: SortedList<u32, u8> = (0..5).map(|x| (x, x as u8)).collect();
+        list.retain_from(2, |k, _| k % 2 == 1);
+        assert_eq!(to_vec(list.iter()), vec![(0, 0), (1, 1), (3, 3)]);
+    }
 }
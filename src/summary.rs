@@ -0,0 +1,482 @@
+use serde::Serialize;
+use stats::{mean, stddev, OnlineStats};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use crate::block::Block;
+use crate::helper::quantile;
+
+/// Totals and per-block quantities collected during a run, so that blocks do not need to keep
+/// their transactions until the end of the run
+#[derive(Default)]
+pub struct RunStats {
+    n_user: u64,
+    n_priced_out_user: u64,
+    n_sent_tx: u64,
+    n_included_tx: u64,
+    n_expired_tx: u64,
+    n_evicted_tx: u64,
+    peak_txpool_size: u64,
+    total_burnt: u128,
+    total_tips: u128,
+    gas_prices: Vec<f64>,
+    fullness: Vec<f64>,
+    txpool_sizes: Vec<f64>,
+    peak_latent_backlog: u64,
+    stopped_at_height: Option<u64>,
+    paid_gas_prices: Histogram,
+    inclusion_delays: Histogram,
+    classes: Vec<ClassStats>,
+}
+
+/// Totals of a class of users, collected during a run
+#[derive(Clone, Default)]
+struct ClassStats {
+    n_user: u64,
+    n_priced_out_user: u64,
+    n_sent_tx: u64,
+    n_included_tx: u64,
+    n_expired_tx: u64,
+    n_evicted_tx: u64,
+    n_retried_tx: u64,
+    n_abandoned_user: u64,
+    paid_gas_prices: Histogram,
+    inclusion_delays: Histogram,
+}
+
+/// Number of occurrences of each value of an integer quantity, from which its distribution is
+/// computed without keeping every value
+#[derive(Clone, Default)]
+pub struct Histogram {
+    counts: BTreeMap<u64, u64>,
+    stats: OnlineStats,
+}
+
+impl Histogram {
+    pub fn add(&mut self, value: u64) {
+        *self.counts.entry(value).or_insert(0) += 1;
+        self.stats.add(value);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Value at position `index` once the values are sorted
+    fn nth(&self, index: u64) -> f64 {
+        let mut seen = 0;
+        for (&value, &count) in self.counts.iter() {
+            seen += count;
+            if index < seen {
+                return value as f64;
+            }
+        }
+        panic!("Index {} out of the histogram", index)
+    }
+
+    /// Quantile interpolated between the closest ranks, like `helper::quantile`
+    fn quantile(&self, q: f64) -> f64 {
+        let rank = q * (self.len() - 1) as f64;
+        let lower = self.nth(rank.floor() as u64);
+        let upper = self.nth(rank.ceil() as u64);
+        lower + (upper - lower) * (rank - rank.floor())
+    }
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats::default()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_block(
+        &mut self,
+        n_user: u64,
+        n_sent_tx: u64,
+        n_expired_tx: u64,
+        n_evicted_tx: u64,
+        txpool_size: u64,
+        gas_price: u64,
+        block: &Block,
+    ) {
+        self.n_user += n_user;
+        self.n_sent_tx += n_sent_tx;
+        self.n_expired_tx += n_expired_tx;
+        self.n_evicted_tx += n_evicted_tx;
+        self.peak_txpool_size = self.peak_txpool_size.max(txpool_size);
+        self.gas_prices.push(gas_price as f64);
+        self.txpool_sizes.push(txpool_size as f64);
+
+        self.n_included_tx += block.tx_count();
+        self.total_burnt += block.burnt() as u128;
+        self.total_tips += block.tips() as u128;
+        self.fullness.push(block.fullness());
+        for tx in block.txs() {
+            let stats = self.class_stats(tx.class());
+            stats.n_included_tx += 1;
+            if let Some(price) = tx.price_paid() {
+                stats.paid_gas_prices.add(price);
+                self.paid_gas_prices.add(price);
+            }
+            if let Some(delay) = tx.inclusion_delay() {
+                let stats = self.class_stats(tx.class());
+                stats.inclusion_delays.add(delay);
+                self.inclusion_delays.add(delay);
+            }
+        }
+    }
+
+    /// Records the users of the `class`th class of users in a block, the arriving users who were
+    /// priced out, and what happened to their transactions
+    pub fn record_class(
+        &mut self,
+        class: usize,
+        n_user: u64,
+        n_sent_tx: u64,
+        n_priced_out_user: u64,
+        n_expired_tx: u64,
+        n_evicted_tx: u64,
+    ) {
+        self.n_priced_out_user += n_priced_out_user;
+        let stats = self.class_stats(class);
+        stats.n_user += n_user;
+        stats.n_priced_out_user += n_priced_out_user;
+        stats.n_sent_tx += n_sent_tx;
+        stats.n_expired_tx += n_expired_tx;
+        stats.n_evicted_tx += n_evicted_tx;
+    }
+
+    /// Records the transactions resubmitted in a block by the priced-out users of the `class`th
+    /// class, and the users who gave up waiting
+    pub fn record_retries(&mut self, class: usize, n_retried_tx: u64, n_abandoned_user: u64) {
+        let stats = self.class_stats(class);
+        stats.n_retried_tx += n_retried_tx;
+        stats.n_abandoned_user += n_abandoned_user;
+    }
+
+    /// Records the number of priced-out users waiting to retry after a block
+    pub fn record_latent_backlog(&mut self, latent_backlog: u64) {
+        self.peak_latent_backlog = self.peak_latent_backlog.max(latent_backlog);
+    }
+
+    /// Records that the run stopped before the block at `height`, as the token price data ran out
+    pub fn record_stop(&mut self, height: u64) {
+        self.stopped_at_height = Some(height);
+    }
+
+    fn class_stats(&mut self, class: usize) -> &mut ClassStats {
+        if self.classes.len() <= class {
+            self.classes.resize(class + 1, ClassStats::default());
+        }
+        &mut self.classes[class]
+    }
+
+    /// Gas price set by the fee mechanism in each block
+    pub fn gas_prices(&self) -> &[f64] {
+        &self.gas_prices
+    }
+
+    /// Fullness of each block
+    pub fn fullness(&self) -> &[f64] {
+        &self.fullness
+    }
+
+    /// Size of the transaction pool after each block
+    pub fn txpool_sizes(&self) -> &[f64] {
+        &self.txpool_sizes
+    }
+}
+
+/// Summary statistics of a set of values
+#[derive(Debug, Serialize)]
+pub struct Distribution {
+    pub mean: f64,
+    pub std: f64,
+    pub min: f64,
+    pub p10: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    pub fn new(values: &[f64]) -> Option<Distribution> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        Some(Distribution {
+            mean: mean(sorted.iter().copied()),
+            std: stddev(sorted.iter().copied()),
+            min: sorted[0],
+            p10: quantile(&sorted, 0.1).unwrap(),
+            median: quantile(&sorted, 0.5).unwrap(),
+            p90: quantile(&sorted, 0.9).unwrap(),
+            p99: quantile(&sorted, 0.99).unwrap(),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+
+    pub fn from_histogram(histogram: &Histogram) -> Option<Distribution> {
+        if histogram.is_empty() {
+            return None;
+        }
+
+        Some(Distribution {
+            mean: histogram.stats.mean(),
+            std: histogram.stats.stddev(),
+            min: histogram.quantile(0.),
+            p10: histogram.quantile(0.1),
+            median: histogram.quantile(0.5),
+            p90: histogram.quantile(0.9),
+            p99: histogram.quantile(0.99),
+            max: histogram.quantile(1.),
+        })
+    }
+}
+
+/// Totals and distributions describing a whole run, written to `summary.json`
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub seed: u64,
+    pub n_blocks: u64,
+    pub n_user: u64,
+    /// Users whose willingness to pay was below the price, and did not send a transaction
+    pub n_priced_out_user: u64,
+    pub n_sent_tx: u64,
+    pub n_included_tx: u64,
+    pub n_expired_tx: u64,
+    pub n_evicted_tx: u64,
+    /// Transactions left in the transaction pool at the end of the run
+    pub n_pending_tx: u64,
+    /// Transactions resubmitted by priced-out users once the price dropped
+    pub n_retried_tx: u64,
+    /// Priced-out users who gave up waiting for the price to drop
+    pub n_abandoned_user: u64,
+    /// Priced-out users still waiting for the price to drop at the end of the run
+    pub n_waiting_user: u64,
+    pub peak_latent_backlog: u64,
+    /// Height at which the run stopped as the token price data ran out, if it did
+    pub stopped_at_height: Option<u64>,
+    pub total_fees: u128,
+    pub total_burnt: u128,
+    pub total_tips: u128,
+    pub fullness: Option<Distribution>,
+    /// Targeted fullness of the fee mechanism, if it has one
+    pub target_fullness: Option<f64>,
+    /// Time during which blocks were fuller than the target [second]
+    pub time_above_target_fullness: Option<u64>,
+    pub fraction_above_target_fullness: Option<f64>,
+    /// Gas price set by the fee mechanism
+    pub gas_price: Option<Distribution>,
+    /// Gas price paid by included transactions
+    pub paid_gas_price: Option<Distribution>,
+    /// Standard deviation of the block to block log returns of the gas price set by the fee
+    /// mechanism
+    pub gas_price_volatility: f64,
+    pub peak_txpool_size: u64,
+    /// Number of blocks between the submission and the inclusion of transactions
+    pub inclusion_delay: Option<Distribution>,
+    /// Breakdown by class of users
+    pub classes: Vec<ClassSummary>,
+}
+
+/// Totals and distributions of a class of users
+#[derive(Debug, Serialize)]
+pub struct ClassSummary {
+    pub label: String,
+    pub n_user: u64,
+    /// Users whose willingness to pay was below the price, and did not send a transaction
+    pub n_priced_out_user: u64,
+    pub n_sent_tx: u64,
+    pub n_included_tx: u64,
+    pub n_expired_tx: u64,
+    pub n_evicted_tx: u64,
+    /// Transactions resubmitted by priced-out users once the price dropped
+    pub n_retried_tx: u64,
+    /// Priced-out users who gave up waiting for the price to drop
+    pub n_abandoned_user: u64,
+    /// Gas price paid by included transactions
+    pub paid_gas_price: Option<Distribution>,
+    /// Number of blocks between the submission and the inclusion of transactions
+    pub inclusion_delay: Option<Distribution>,
+}
+
+impl Summary {
+    pub fn new(
+        stats: &RunStats,
+        n_pending_tx: u64,
+        n_waiting_user: u64,
+        target_fullness: Option<f64>,
+        block_time: u64,
+        seed: u64,
+        class_labels: &[&str],
+    ) -> Summary {
+        let n_blocks = stats.fullness.len() as u64;
+        let n_above_target = target_fullness
+            .map(|target| stats.fullness.iter().filter(|&&x| x > target).count() as u64);
+
+        let classes = class_labels
+            .iter()
+            .enumerate()
+            .map(|(class, &label)| {
+                let class_stats = stats.classes.get(class).cloned().unwrap_or_default();
+
+                ClassSummary {
+                    label: label.to_string(),
+                    n_user: class_stats.n_user,
+                    n_priced_out_user: class_stats.n_priced_out_user,
+                    n_sent_tx: class_stats.n_sent_tx,
+                    n_included_tx: class_stats.n_included_tx,
+                    n_expired_tx: class_stats.n_expired_tx,
+                    n_evicted_tx: class_stats.n_evicted_tx,
+                    n_retried_tx: class_stats.n_retried_tx,
+                    n_abandoned_user: class_stats.n_abandoned_user,
+                    paid_gas_price: Distribution::from_histogram(&class_stats.paid_gas_prices),
+                    inclusion_delay: Distribution::from_histogram(&class_stats.inclusion_delays),
+                }
+            })
+            .collect();
+
+        let log_returns: Vec<f64> = stats
+            .gas_prices
+            .windows(2)
+            .filter(|w| w[0] > 0. && w[1] > 0.)
+            .map(|w| (w[1] / w[0]).ln())
+            .collect();
+
+        Summary {
+            seed,
+            n_blocks,
+            n_user: stats.n_user,
+            n_priced_out_user: stats.n_priced_out_user,
+            n_sent_tx: stats.n_sent_tx,
+            n_included_tx: stats.n_included_tx,
+            n_expired_tx: stats.n_expired_tx,
+            n_evicted_tx: stats.n_evicted_tx,
+            n_pending_tx,
+            n_retried_tx: stats.classes.iter().map(|c| c.n_retried_tx).sum(),
+            n_abandoned_user: stats.classes.iter().map(|c| c.n_abandoned_user).sum(),
+            n_waiting_user,
+            peak_latent_backlog: stats.peak_latent_backlog,
+            stopped_at_height: stats.stopped_at_height,
+            total_fees: stats.total_burnt + stats.total_tips,
+            total_burnt: stats.total_burnt,
+            total_tips: stats.total_tips,
+            fullness: Distribution::new(&stats.fullness),
+            target_fullness,
+            time_above_target_fullness: n_above_target.map(|n| n * block_time),
+            fraction_above_target_fullness: n_above_target
+                .filter(|_| n_blocks > 0)
+                .map(|n| n as f64 / n_blocks as f64),
+            gas_price: Distribution::new(&stats.gas_prices),
+            paid_gas_price: Distribution::from_histogram(&stats.paid_gas_prices),
+            gas_price_volatility: if log_returns.is_empty() {
+                0.
+            } else {
+                stddev(log_returns.into_iter())
+            },
+            peak_txpool_size: stats.peak_txpool_size,
+            inclusion_delay: Distribution::from_histogram(&stats.inclusion_delays),
+            classes,
+        }
+    }
+
+    pub fn write(&self, path: &Path) {
+        let file = File::create(path).expect("Could not create the summary file");
+        serde_json::to_writer_pretty(file, self).expect("Could not write the summary");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution, Histogram};
+    use crate::config::SimulatorConfig;
+    use crate::simulator::FeeMarketSimulator;
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_distribution() {
+        let dist = Distribution::new(&[3., 1., 2.]).unwrap();
+        assert_eq!(dist.min, 1.);
+        assert_eq!(dist.median, 2.);
+        assert_eq!(dist.max, 3.);
+        assert_eq!(dist.mean, 2.);
+
+        assert!(Distribution::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let values = [5, 1, 3, 3, 8, 1, 3];
+        let mut histogram = Histogram::default();
+        for &value in values.iter() {
+            histogram.add(value);
+        }
+
+        let from_values =
+            Distribution::new(&values.iter().map(|&x| x as f64).collect::<Vec<f64>>()).unwrap();
+        let from_histogram = Distribution::from_histogram(&histogram).unwrap();
+        assert_eq!(from_histogram.min, from_values.min);
+        assert_eq!(from_histogram.p10, from_values.p10);
+        assert_eq!(from_histogram.median, from_values.median);
+        assert_eq!(from_histogram.p90, from_values.p90);
+        assert_eq!(from_histogram.p99, from_values.p99);
+        assert_eq!(from_histogram.max, from_values.max);
+        assert!((from_histogram.mean - from_values.mean).abs() < 1e-9);
+        assert!((from_histogram.std - from_values.std).abs() < 1e-9);
+
+        assert!(Distribution::from_histogram(&Histogram::default()).is_none());
+    }
+
+    #[test]
+    fn test_priced_out_users() {
+        let output_dir = std::env::temp_dir().join(format!("priced_out_{}", std::process::id()));
+        // Users priced out by a high initial price wait for it to drop and resubmit
+        let raw = json!({
+            "output_dir": output_dir,
+            "block_gas_limit": 1_000,
+            "block_time": 600,
+            "mechanism": "autoprice",
+            "initial_price": 90,
+            "control_range": 1,
+            "target_fullness": 0.5,
+            "price_adjustment_rate": 0.2,
+            "demand_curve": {"shape": "linear", "min_price": 0, "max_price": 100},
+            "demand_profile": {"n_blocks": 20, "components": [{"type": "constant", "value": 20}]},
+            "tx_gas_used": 10,
+            "patience": 5,
+            "seed": 1,
+        });
+        let config = SimulatorConfig::from_value(&raw, Path::new("config.toml")).unwrap();
+        let summary = FeeMarketSimulator::from_config(&config)
+            .with_progress_bar(false)
+            .run_classes(config.demand_profiles(), output_dir.clone());
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        assert!(summary.n_priced_out_user > 0);
+        assert!(summary.n_retried_tx > 0);
+        // Every arriving user either sends a transaction right away or is priced out
+        assert_eq!(
+            summary.n_user,
+            summary.n_priced_out_user + summary.n_sent_tx - summary.n_retried_tx
+        );
+        let class = &summary.classes[0];
+        assert_eq!(
+            class.n_user,
+            class.n_priced_out_user + class.n_sent_tx - class.n_retried_tx
+        );
+        assert_eq!(class.n_priced_out_user, summary.n_priced_out_user);
+    }
+}
@@ -0,0 +1,407 @@
+use indicatif::ProgressBar;
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ConfigError, SimulatorConfig};
+use crate::simulator::{random_seed, FeeMarketSimulator};
+use crate::summary::Summary;
+
+/// Parameter sweep, as read from a TOML sweep spec
+#[derive(Debug, Deserialize)]
+pub struct SweepSpec {
+    /// Directory the runs and the index are written to
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
+    /// Values taken by each swept config key
+    pub parameters: BTreeMap<String, SweepValues>,
+}
+
+/// Values taken by a swept config key
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SweepValues {
+    /// Explicit list of values
+    List(Vec<Value>),
+    /// Values from `start` to `stop` inclusive, in increments of `step`
+    Range {
+        start: Value,
+        stop: Value,
+        step: Value,
+    },
+}
+
+fn default_output_dir() -> PathBuf {
+    PathBuf::from("sweep")
+}
+
+impl SweepValues {
+    /// Expands the values, ranges of integers giving integers
+    pub fn values(&self) -> Result<Vec<Value>, String> {
+        match self {
+            SweepValues::List(values) => Ok(values.clone()),
+            SweepValues::Range { start, stop, step } => {
+                if let (Some(start), Some(stop), Some(step)) =
+                    (start.as_i64(), stop.as_i64(), step.as_i64())
+                {
+                    if step <= 0 {
+                        return Err("range step must be positive".to_string());
+                    }
+                    return Ok((start..=stop)
+                        .step_by(step as usize)
+                        .map(Value::from)
+                        .collect());
+                }
+
+                match (start.as_f64(), stop.as_f64(), step.as_f64()) {
+                    (Some(start), Some(stop), Some(step)) => {
+                        if step <= 0. {
+                            return Err("range step must be positive".to_string());
+                        }
+                        // Tolerate rounding errors so that `stop` is included
+                        let n_step = ((stop - start) / step + 1e-9).floor();
+                        if n_step < 0. {
+                            return Ok(Vec::new());
+                        }
+                        Ok((0..=n_step as u64)
+                            .map(|i| round(start + i as f64 * step))
+                            .map(Value::from)
+                            .collect())
+                    }
+                    _ => Err("range bounds and step must be numbers".to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Rounds off the error accumulated when stepping through a range of floats
+fn round(x: f64) -> f64 {
+    (x * 1e12).round() / 1e12
+}
+
+/// Single run of a sweep
+pub struct SweepRun {
+    /// Values of the swept keys, in the order of `Sweep::keys`
+    pub parameters: Vec<Value>,
+    pub config: SimulatorConfig,
+}
+
+/// Cartesian product of the values of the swept keys, applied to a base config
+pub struct Sweep {
+    output_dir: PathBuf,
+    keys: Vec<String>,
+    runs: Vec<SweepRun>,
+}
+
+impl Sweep {
+    /// Reads the base config and the sweep spec, and builds the config of every run. All runs use
+    /// the seed of the base config, or the same random seed if it has none, so that they only
+    /// differ by the swept parameters.
+    pub fn from_files(config_path: &Path, spec_path: &Path) -> Result<Sweep, ConfigError> {
+        let spec_error = |message: String| ConfigError::new(spec_path, message);
+
+        let mut settings = config::Config::default();
+        settings
+            .merge(config::File::from(spec_path))
+            .map_err(|e| spec_error(e.to_string()))?;
+        let spec: SweepSpec = settings.try_into().map_err(|e| spec_error(e.to_string()))?;
+        if spec.parameters.is_empty() {
+            return Err(spec_error("`parameters` must not be empty".to_string()));
+        }
+
+        let mut base = SimulatorConfig::read_value(config_path)?;
+        let base_config = SimulatorConfig::from_value(&base, config_path)?;
+        for key in base_config.unknown_keys(&base) {
+            eprintln!(
+                "Warning: {}: unknown key `{}` is ignored",
+                config_path.display(),
+                key
+            );
+        }
+        // Each run gives a single summary in the index
+        if base_config.replicas > 1 {
+            return Err(ConfigError::new(
+                config_path,
+                "`replicas` must be 1 in the base config of a sweep".to_string(),
+            ));
+        }
+        if base_config.seed.is_none() {
+            base["seed"] = Value::from(random_seed());
+        }
+
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        for (key, sweep_values) in spec.parameters.iter() {
+            let key_values = sweep_values
+                .values()
+                .map_err(|e| spec_error(format!("{} for key `{}`", e, key)))?;
+            if key_values.is_empty() {
+                return Err(spec_error(format!("no values for key `{}`", key)));
+            }
+            if key == "replicas" {
+                return Err(spec_error("key `replicas` cannot be swept".to_string()));
+            }
+            keys.push(key.clone());
+            values.push(key_values);
+        }
+
+        let spec_dir = spec_path.parent().unwrap_or_else(|| Path::new(""));
+        let output_dir = spec_dir.join(&spec.output_dir);
+
+        let runs = values
+            .into_iter()
+            .multi_cartesian_product()
+            .enumerate()
+            .map(|(i, parameters)| {
+                let mut raw = base.clone();
+                for (key, value) in keys.iter().zip(parameters.iter()) {
+                    set_key(&mut raw, key, value.clone());
+                }
+                let mut config = SimulatorConfig::from_value(&raw, config_path).map_err(|e| {
+                    let parameters: Vec<String> = keys
+                        .iter()
+                        .zip(parameters.iter())
+                        .map(|(key, value)| format!("{} = {}", key, value))
+                        .collect();
+                    spec_error(format!("invalid run {}: {}", parameters.join(", "), e))
+                })?;
+                // Keys are checked once set, as optional tables are missing from the base config
+                if let Some(key) = unknown_swept_key(&keys, &config.unknown_keys(&raw)) {
+                    return Err(spec_error(format!("unknown config key `{}`", key)));
+                }
+                config.output_dir = output_dir.join(run_name(i));
+                Ok(SweepRun { parameters, config })
+            })
+            .collect::<Result<Vec<SweepRun>, ConfigError>>()?;
+
+        Ok(Sweep {
+            output_dir,
+            keys,
+            runs,
+        })
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub fn runs(&self) -> &[SweepRun] {
+        &self.runs
+    }
+
+    /// Runs the sweep in parallel, each run writing to its own subdirectory, then writes
+    /// `index.csv` with the parameters and headline metrics of every run
+    pub fn run(&self) -> Vec<Summary> {
+        fs::create_dir_all(&self.output_dir).expect("Could not create the output directory");
+
+        let bar = ProgressBar::new(self.runs.len() as u64);
+
+        let summaries: Vec<Summary> = self
+            .runs
+            .par_iter()
+            .map(|run| {
+                let demand_profiles = run.config.demand_profiles();
+                let summary = FeeMarketSimulator::from_config(&run.config)
+                    .with_progress_bar(false)
+                    .run_classes(demand_profiles, run.config.output_dir.clone());
+                bar.inc(1);
+                summary
+            })
+            .collect();
+
+        bar.finish();
+
+        self.write_index(&summaries);
+
+        summaries
+    }
+
+    fn write_index(&self, summaries: &[Summary]) {
+        let mut writer = csv::Writer::from_path(self.output_dir.join("index.csv"))
+            .expect("Could not create the sweep index");
+
+        let mut header = vec!["run"];
+        header.extend(self.keys.iter().map(String::as_str));
+        header.extend(&[
+            "seed",
+            "n_included_tx",
+            "n_priced_out_user",
+            "total_fees",
+            "mean_fullness",
+            "fraction_above_target_fullness",
+            "mean_gas_price",
+            "gas_price_volatility",
+            "peak_txpool_size",
+            "median_inclusion_delay",
+        ]);
+        writer.write_record(&header).unwrap();
+
+        for (i, (run, summary)) in self.runs.iter().zip(summaries.iter()).enumerate() {
+            let mut record = vec![run_name(i)];
+            record.extend(run.parameters.iter().map(format_value));
+            record.extend(vec![
+                summary.seed.to_string(),
+                summary.n_included_tx.to_string(),
+                summary.n_priced_out_user.to_string(),
+                summary.total_fees.to_string(),
+                format_option(summary.fullness.as_ref().map(|x| x.mean)),
+                format_option(summary.fraction_above_target_fullness),
+                format_option(summary.gas_price.as_ref().map(|x| x.mean)),
+                summary.gas_price_volatility.to_string(),
+                summary.peak_txpool_size.to_string(),
+                format_option(summary.inclusion_delay.as_ref().map(|x| x.median)),
+            ]);
+            writer.write_record(&record).unwrap();
+        }
+
+        writer.flush().expect("Could not write the sweep index");
+    }
+}
+
+fn run_name(i: usize) -> String {
+    format!("run_{:04}", i)
+}
+
+/// Returns the first swept key which is unknown, or belongs to an unknown table
+fn unknown_swept_key<'a>(keys: &'a [String], unknown_keys: &[String]) -> Option<&'a String> {
+    keys.iter().find(|key| {
+        unknown_keys
+            .iter()
+            .any(|unknown| *key == unknown || key.starts_with(&format!("{}.", unknown)))
+    })
+}
+
+/// Sets the value at a dotted key, creating the tables along the way
+fn set_key(value: &mut Value, key: &str, new_value: Value) {
+    let target = key.split('.').fold(value, |value, part| {
+        if !value.is_object() {
+            *value = Value::Object(serde_json::Map::new());
+        }
+        value
+            .as_object_mut()
+            .unwrap()
+            .entry(part)
+            .or_insert(Value::Null)
+    });
+    *target = new_value;
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    value.map(|x| x.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_key, unknown_swept_key, Sweep, SweepValues};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_range_values() {
+        let ints = SweepValues::Range {
+            start: json!(10),
+            stop: json!(50),
+            step: json!(20),
+        };
+        assert_eq!(
+            ints.values().unwrap(),
+            vec![json!(10), json!(30), json!(50)]
+        );
+
+        let floats = SweepValues::Range {
+            start: json!(0.005),
+            stop: json!(0.02),
+            step: json!(0.005),
+        };
+        assert_eq!(
+            floats.values().unwrap(),
+            vec![json!(0.005), json!(0.01), json!(0.015), json!(0.02)]
+        );
+
+        let bad_step = SweepValues::Range {
+            start: json!(1),
+            stop: json!(2),
+            step: json!(0),
+        };
+        assert!(bad_step.values().is_err());
+    }
+
+    #[test]
+    fn test_dotted_keys() {
+        let mut value = json!({"a": 1, "b": {"c": 2}, "f": null});
+        set_key(&mut value, "b.c", json!(3));
+        set_key(&mut value, "d.e", json!(4));
+        set_key(&mut value, "f.g", json!(5));
+
+        assert_eq!(
+            value,
+            json!({"a": 1, "b": {"c": 3}, "d": {"e": 4}, "f": {"g": 5}})
+        );
+    }
+
+    #[test]
+    fn test_unknown_swept_key() {
+        let keys = vec!["oracle.noise".to_string(), "b.c".to_string()];
+        assert_eq!(unknown_swept_key(&keys, &[]), None);
+        assert_eq!(unknown_swept_key(&keys, &["b".to_string()]), Some(&keys[1]));
+        assert_eq!(unknown_swept_key(&keys, &["oracle.nois".to_string()]), None);
+        assert_eq!(
+            unknown_swept_key(&keys, &["oracle.noise".to_string()]),
+            Some(&keys[0])
+        );
+    }
+
+    #[test]
+    fn test_from_files() {
+        let dir = std::env::temp_dir().join(format!("sweep_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = "block_gas_limit = 10\n\
+                    block_time = 600\n\
+                    mechanism = \"first_price\"\n\
+                    min_gas_price = 1\n\
+                    tx_gas_used = 1\n\
+                    demand_curve = { shape = \"linear\", min_price = 0, max_price = 100 }\n\
+                    [demand_profile]\n\
+                    n_blocks = 2\n\
+                    components = [{ type = \"constant\", value = 10 }]\n";
+        let sweep = |config: &str, spec: &str| {
+            let (config_path, spec_path) = (dir.join("config.toml"), dir.join("sweep.toml"));
+            fs::write(&config_path, config).unwrap();
+            fs::write(&spec_path, spec).unwrap();
+            Sweep::from_files(&config_path, &spec_path).map(|sweep| sweep.runs().len())
+        };
+
+        let results = [
+            sweep(base, "[parameters]\nmin_gas_price = [1, 2]\n"),
+            sweep(base, "[parameters]\n"),
+            sweep(
+                &format!("replicas = 2\n{}", base),
+                "[parameters]\nmin_gas_price = [1, 2]\n",
+            ),
+            sweep(base, "[parameters]\nreplicas = [1, 2]\n"),
+        ];
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap(), &2);
+        for (result, message) in results[1..].iter().zip(&[
+            "`parameters` must not be empty",
+            "`replicas` must be 1",
+            "`replicas` cannot be swept",
+        ]) {
+            let error = result.as_ref().unwrap_err().to_string();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use rand::Rng;
+use rand_distr::{Distribution, Poisson, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::helper::LinearInterpolator;
+
+const SECONDS_IN_YEAR: f64 = 365. * 86_400.;
+
+/// Stochastic model of the token price, selected with the `model` key. Drifts, volatilities and
+/// jump intensities are annualized.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum TokenPriceModel {
+    /// Geometric Brownian motion
+    Gbm { volatility: f64 },
+    /// Geometric Brownian motion with jumps arriving `jump_intensity` times per year on average,
+    /// whose log sizes are normally distributed with mean `jump_mean` and standard deviation
+    /// `jump_std`
+    JumpDiffusion {
+        volatility: f64,
+        jump_intensity: f64,
+        jump_mean: f64,
+        jump_std: f64,
+    },
+    /// Geometric Brownian motion whose volatility switches between `volatilities`, starting with
+    /// the first one. Each regime lasts `mean_regime_duration` seconds on average, before
+    /// switching to one of the others at random.
+    RegimeSwitching {
+        volatilities: Vec<f64>,
+        mean_regime_duration: f64,
+    },
+}
+
+impl TokenPriceModel {
+    fn validate(&self) -> Result<(), String> {
+        let check_volatility = |volatility: f64| {
+            if volatility >= 0. {
+                Ok(())
+            } else {
+                Err("volatility must not be negative".to_string())
+            }
+        };
+        match self {
+            TokenPriceModel::Gbm { volatility } => check_volatility(*volatility),
+            TokenPriceModel::JumpDiffusion {
+                volatility,
+                jump_intensity,
+                jump_std,
+                ..
+            } => {
+                check_volatility(*volatility)?;
+                if *jump_intensity < 0. || *jump_std < 0. {
+                    return Err(
+                        "jump intensity and standard deviation must not be negative".to_string()
+                    );
+                }
+                Ok(())
+            }
+            TokenPriceModel::RegimeSwitching {
+                volatilities,
+                mean_regime_duration,
+            } => {
+                if volatilities.is_empty() {
+                    return Err("at least one volatility must be given".to_string());
+                }
+                volatilities.iter().try_for_each(|&v| check_volatility(v))?;
+                if *mean_regime_duration <= 0. {
+                    return Err("mean regime duration must be positive".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Generator of synthetic token price paths, starting at `initial_price` and sampled every
+/// `interval` seconds
+#[derive(Clone, Debug)]
+pub struct TokenPriceGenerator {
+    initial_price: f64,
+    drift: f64,
+    interval: u64,
+    model: TokenPriceModel,
+}
+
+impl TokenPriceGenerator {
+    pub fn new(
+        initial_price: f64,
+        drift: f64,
+        interval: u64,
+        model: TokenPriceModel,
+    ) -> Result<TokenPriceGenerator, String> {
+        if initial_price <= 0. {
+            return Err("initial price must be positive".to_string());
+        }
+        if interval == 0 {
+            return Err("interval must be positive".to_string());
+        }
+        model.validate()?;
+
+        Ok(TokenPriceGenerator {
+            initial_price,
+            drift,
+            interval,
+            model,
+        })
+    }
+
+    /// Generates a price path covering `duration` seconds, with the time in seconds since the
+    /// start as the x axis
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R, duration: u64) -> LinearInterpolator {
+        let n_steps = duration.div_ceil(self.interval).max(1);
+        let dt = self.interval as f64 / SECONDS_IN_YEAR;
+
+        let mut regime = 0;
+        let mut price = self.initial_price;
+        let mut times = vec![0.];
+        let mut prices = vec![price];
+
+        for step in 1..=n_steps {
+            let volatility = match &self.model {
+                TokenPriceModel::Gbm { volatility } => *volatility,
+                TokenPriceModel::JumpDiffusion { volatility, .. } => *volatility,
+                TokenPriceModel::RegimeSwitching {
+                    volatilities,
+                    mean_regime_duration,
+                } => {
+                    let switch_probability =
+                        1. - (-(self.interval as f64) / mean_regime_duration).exp();
+                    if volatilities.len() > 1 && rng.gen::<f64>() < switch_probability {
+                        // Any regime but the current one
+                        regime =
+                            (regime + rng.gen_range(1, volatilities.len())) % volatilities.len();
+                    }
+                    volatilities[regime]
+                }
+            };
+
+            let z: f64 = rng.sample(StandardNormal);
+            let mut log_return =
+                (self.drift - volatility.powi(2) / 2.) * dt + volatility * dt.sqrt() * z;
+
+            if let TokenPriceModel::JumpDiffusion {
+                jump_intensity,
+                jump_mean,
+                jump_std,
+                ..
+            } = self.model
+            {
+                if jump_intensity > 0. {
+                    let n_jumps: u64 = Poisson::new(jump_intensity * dt).unwrap().sample(rng);
+                    for _ in 0..n_jumps {
+                        let z: f64 = rng.sample(StandardNormal);
+                        log_return += jump_mean + jump_std * z;
+                    }
+                }
+            }
+
+            price *= log_return.exp();
+            times.push((step * self.interval) as f64);
+            prices.push(price);
+        }
+
+        LinearInterpolator::new(&times, &prices)
+    }
+}
+
+/// Behavior when a block falls outside of the token price data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRange {
+    /// Stop the run at the last block covered by the data
+    #[default]
+    Stop,
+    /// Hold the first or last price of the data
+    Hold,
+    /// Start over from the other end of the data
+    Loop,
+    /// Go back and forth through the data
+    Mirror,
+    /// Reject configs whose token price data does not cover the whole run
+    Error,
+}
+
+/// Maps the time of the simulation to timestamps of the token price data. The run starts at
+/// timestamp `start`, or `offset` seconds after the first timestamp of the data if not given, and
+/// every second of the simulation covers `time_scale` seconds of the data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeAlignment {
+    pub start: Option<f64>,
+    pub offset: f64,
+    pub time_scale: f64,
+    pub out_of_range: OutOfRange,
+}
+
+impl Default for TimeAlignment {
+    fn default() -> TimeAlignment {
+        TimeAlignment {
+            start: None,
+            offset: 0.,
+            time_scale: 1.,
+            out_of_range: OutOfRange::Stop,
+        }
+    }
+}
+
+impl TimeAlignment {
+    /// Timestamp of the data read at the end of a run of `duration` seconds, for data starting at
+    /// timestamp 0 such as generated price paths
+    pub fn end(&self, duration: u64) -> f64 {
+        self.start.unwrap_or(self.offset) + duration as f64 * self.time_scale
+    }
+
+    /// Timestamp of the data at which the token price is read at `time`, or `None` if the run
+    /// should stop there, i.e. once out of the data with `OutOfRange::Stop` or `OutOfRange::Error`
+    pub fn timestamp(&self, data: &LinearInterpolator, time: u64) -> Option<f64> {
+        let (xmin, xmax) = (data.xmin(), data.xmax());
+        let start = self.start.unwrap_or(xmin + self.offset);
+        let timestamp = start + time as f64 * self.time_scale;
+        if xmin <= timestamp && timestamp <= xmax {
+            return Some(timestamp);
+        }
+
+        let span = xmax - xmin;
+        match self.out_of_range {
+            OutOfRange::Stop | OutOfRange::Error => None,
+            OutOfRange::Hold => Some(timestamp.max(xmin).min(xmax)),
+            OutOfRange::Loop if span > 0. => Some(xmin + (timestamp - xmin).rem_euclid(span)),
+            OutOfRange::Mirror if span > 0. => {
+                let position = (timestamp - xmin).rem_euclid(2. * span);
+                Some(xmin + span - (position - span).abs())
+            }
+            OutOfRange::Loop | OutOfRange::Mirror => Some(xmin),
+        }
+    }
+
+    /// Checks that the data covers every timestamp read by a run whose last block is at
+    /// `duration` seconds
+    pub fn check_coverage(&self, data: &LinearInterpolator, duration: u64) -> Result<(), String> {
+        let start = self.start.unwrap_or(data.xmin() + self.offset);
+        let end = start + duration as f64 * self.time_scale;
+        if start < data.xmin() || end > data.xmax() {
+            return Err(format!(
+                "the token price data covers timestamps {} to {}, but the run reads {} to {}",
+                data.xmin(),
+                data.xmax(),
+                start,
+                end
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutOfRange, TimeAlignment, TokenPriceGenerator, TokenPriceModel, SECONDS_IN_YEAR};
+    use crate::helper::LinearInterpolator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use stats::{mean, stddev};
+
+    #[test]
+    fn test_gbm_moments() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let interval = 86_400;
+        let generator = TokenPriceGenerator::new(
+            100.,
+            0.1,
+            interval,
+            TokenPriceModel::Gbm { volatility: 0.8 },
+        )
+        .unwrap();
+
+        // Daily log returns over 100 years
+        let duration = 100 * 365 * 86_400;
+        let path = generator.generate(&mut rng, duration);
+        assert_eq!(path.xmax(), duration as f64);
+        let log_returns: Vec<f64> = (0..duration / interval)
+            .map(|i| {
+                let t = (i * interval) as f64;
+                (path.interpolate(t + interval as f64) / path.interpolate(t)).ln()
+            })
+            .collect();
+
+        let dt = interval as f64 / SECONDS_IN_YEAR;
+        let volatility = stddev(log_returns.iter().copied()) / dt.sqrt();
+        let drift = mean(log_returns.iter().copied()) / dt + volatility.powi(2) / 2.;
+        assert!((volatility - 0.8).abs() < 0.02);
+        assert!((drift - 0.1).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_regimes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let regimes = TokenPriceModel::RegimeSwitching {
+            volatilities: vec![0., 1.],
+            mean_regime_duration: 1e12,
+        };
+        // Never leaves the first regime, without volatility nor drift
+        let generator = TokenPriceGenerator::new(5., 0., 3_600, regimes).unwrap();
+        let path = generator.generate(&mut rng, 86_400);
+        assert!((path.interpolate(86_400.) - 5.).abs() < 1e-9);
+
+        // The path covers the part read by a run starting a day in, twice as fast
+        let alignment = TimeAlignment {
+            offset: 86_400.,
+            time_scale: 2.,
+            ..TimeAlignment::default()
+        };
+        let path = generator.generate(&mut rng, alignment.end(86_400).ceil() as u64);
+        assert_eq!(alignment.timestamp(&path, 86_400), Some(3. * 86_400.));
+
+        let gbm = || TokenPriceModel::Gbm { volatility: 1. };
+        assert!(TokenPriceGenerator::new(0., 0., 3_600, gbm()).is_err());
+        assert!(TokenPriceGenerator::new(1., 0., 0, gbm()).is_err());
+    }
+
+    #[test]
+    fn test_time_alignment() {
+        // Timestamps 100 to 200
+        let data = LinearInterpolator::new(&[100., 200.], &[1., 2.]);
+        let timestamps = |out_of_range: OutOfRange| -> Vec<Option<f64>> {
+            let alignment = TimeAlignment {
+                start: None,
+                offset: 50.,
+                time_scale: 2.,
+                out_of_range,
+            };
+            [0, 25, 40, 90]
+                .iter()
+                .map(|&time| alignment.timestamp(&data, time))
+                .collect()
+        };
+
+        assert_eq!(
+            timestamps(OutOfRange::Stop),
+            vec![Some(150.), Some(200.), None, None]
+        );
+        assert_eq!(
+            timestamps(OutOfRange::Hold),
+            vec![Some(150.), Some(200.), Some(200.), Some(200.)]
+        );
+        assert_eq!(
+            timestamps(OutOfRange::Loop),
+            vec![Some(150.), Some(200.), Some(130.), Some(130.)]
+        );
+        assert_eq!(
+            timestamps(OutOfRange::Mirror),
+            vec![Some(150.), Some(200.), Some(170.), Some(130.)]
+        );
+        assert_eq!(
+            timestamps(OutOfRange::Error),
+            vec![Some(150.), Some(200.), None, None]
+        );
+
+        let from_start = |start: f64| TimeAlignment {
+            start: Some(start),
+            offset: 50.,
+            out_of_range: OutOfRange::Error,
+            ..TimeAlignment::default()
+        };
+        // The start timestamp takes precedence over the offset
+        assert_eq!(from_start(120.).timestamp(&data, 30), Some(150.));
+        assert!(from_start(120.).check_coverage(&data, 80).is_ok());
+        assert!(from_start(120.).check_coverage(&data, 90).is_err());
+        assert!(from_start(90.).check_coverage(&data, 0).is_err());
+    }
+}
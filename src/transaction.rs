@@ -1,28 +1,114 @@
 // use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::packing::BlockPacker;
 use crate::sorted_list::SortedList;
 
-static mut TX_COUNTER: u64 = 0;
+/// Time-to-live of a transaction, measured from its submission
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ttl {
+    Blocks(u64),
+    Seconds(u64),
+}
+
+impl Ttl {
+    /// Returns the expiry of a transaction submitted at the given height and time
+    pub fn expiry(&self, height: u64, time: u64) -> Expiry {
+        match *self {
+            Ttl::Blocks(ttl) => Expiry::Height(height + ttl),
+            Ttl::Seconds(ttl) => Expiry::Time(time + ttl),
+        }
+    }
+}
+
+/// Last height or time at which a transaction can be included
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Expiry {
+    Height(u64),
+    Time(u64),
+}
+
+impl Expiry {
+    pub fn is_expired(&self, height: u64, time: u64) -> bool {
+        match *self {
+            Expiry::Height(expiry_height) => height > expiry_height,
+            Expiry::Time(expiry_time) => time > expiry_time,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Transaction {
     id: u64,
     gas_used: u64,
     gas_price: u64,
+    max_priority_fee: Option<u64>,
+    wtp: f64,
+    submitted_height: u64,
+    included_height: Option<u64>,
+    price_paid: Option<u64>,
+    expiry: Option<Expiry>,
+    class: usize,
 }
 
 impl Transaction {
     pub fn new(gas_used: u64, gas_price: u64) -> Transaction {
-        unsafe {
-            let result = Transaction {
-                gas_used,
-                gas_price,
-                id: TX_COUNTER,
-            };
-            TX_COUNTER += 1;
-            return result;
+        Transaction::with_fees(gas_used, gas_price, None)
+    }
+
+    /// Creates an EIP-1559 transaction, where `max_fee` is the maximum gas price the sender is
+    /// willing to pay in total and `max_priority_fee` the maximum tip on top of the base fee
+    pub fn new_eip1559(gas_used: u64, max_fee: u64, max_priority_fee: u64) -> Transaction {
+        Transaction::with_fees(gas_used, max_fee, Some(max_priority_fee))
+    }
+
+    fn with_fees(gas_used: u64, gas_price: u64, max_priority_fee: Option<u64>) -> Transaction {
+        Transaction {
+            gas_used,
+            gas_price,
+            max_priority_fee,
+            wtp: gas_price as f64,
+            submitted_height: 0,
+            included_height: None,
+            price_paid: None,
+            expiry: None,
+            class: 0,
+            id: 0,
         }
     }
 
+    /// Sets the height or time after which the transaction is dropped from the pool
+    pub fn with_expiry(mut self, expiry: Expiry) -> Transaction {
+        self.expiry = Some(expiry);
+        self
+    }
+
+    /// Sets the index of the class of users the sender belongs to
+    pub fn with_class(mut self, class: usize) -> Transaction {
+        self.class = class;
+        self
+    }
+
+    /// Index of the class of users the sender belongs to
+    pub fn class(&self) -> usize {
+        self.class
+    }
+
+    pub fn expiry(&self) -> Option<Expiry> {
+        self.expiry
+    }
+
+    pub fn is_expired(&self, height: u64, time: u64) -> bool {
+        self.expiry
+            .is_some_and(|expiry| expiry.is_expired(height, time))
+    }
+
+    /// Identifier of the transaction, given by the pool it was added to
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     pub fn fee(&self) -> u64 {
         self.gas_used * self.gas_price
     }
@@ -31,9 +117,80 @@ impl Transaction {
         self.gas_used
     }
 
+    /// Gas price bid by the sender. For EIP-1559 transactions, this is the max fee per gas
     pub fn gas_price(&self) -> u64 {
         self.gas_price
     }
+
+    /// Key by which the block producer orders transactions, i.e. the max priority fee for
+    /// EIP-1559 transactions and the gas price otherwise
+    pub fn priority(&self) -> u64 {
+        self.max_priority_fee.unwrap_or(self.gas_price)
+    }
+
+    /// Whether the transaction can pay the given base fee
+    pub fn is_includable(&self, base_fee: u64) -> bool {
+        self.gas_price >= base_fee
+    }
+
+    /// Gas price received by the block producer on top of the base fee
+    pub fn priority_fee(&self, base_fee: u64) -> u64 {
+        let available = self.gas_price.saturating_sub(base_fee);
+        match self.max_priority_fee {
+            Some(max_priority_fee) => max_priority_fee.min(available),
+            None => available,
+        }
+    }
+
+    /// Gas price actually paid by the sender when included under the given base fee
+    pub fn effective_gas_price(&self, base_fee: u64) -> u64 {
+        base_fee + self.priority_fee(base_fee)
+    }
+
+    /// Records the submission of the transaction at the given height, by a user willing to pay at
+    /// most `wtp` per gas
+    pub fn submitted(mut self, wtp: f64, height: u64) -> Transaction {
+        self.wtp = wtp;
+        self.submitted_height = height;
+        self
+    }
+
+    /// Records the inclusion of the transaction at the given height, charged `gas_price` per gas
+    pub fn include(&mut self, height: u64, gas_price: u64) {
+        self.included_height = Some(height);
+        self.price_paid = Some(gas_price);
+    }
+
+    /// Maximum gas price the sender is willing to pay, in tokens, without rounding. Defaults to the
+    /// gas price if the submission was not recorded
+    pub fn wtp(&self) -> f64 {
+        self.wtp
+    }
+
+    pub fn submitted_height(&self) -> u64 {
+        self.submitted_height
+    }
+
+    pub fn included_height(&self) -> Option<u64> {
+        self.included_height
+    }
+
+    /// Number of blocks between the submission and the inclusion of the transaction
+    pub fn inclusion_delay(&self) -> Option<u64> {
+        self.included_height
+            .map(|height| height - self.submitted_height)
+    }
+
+    /// Difference between what the sender was willing to pay and what they paid, if included
+    pub fn surplus(&self) -> Option<f64> {
+        self.price_paid
+            .map(|price| (self.wtp - price as f64) * self.gas_used as f64)
+    }
+
+    /// Gas price charged to the transaction, if it was included
+    pub fn price_paid(&self) -> Option<u64> {
+        self.price_paid
+    }
 }
 
 impl PartialEq for Transaction {
@@ -42,44 +199,237 @@ impl PartialEq for Transaction {
     }
 }
 
+/// Decides which transactions are dropped when the transaction pool is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Evict the transactions with the lowest priority, including new ones
+    #[default]
+    LowestPrice,
+    /// Evict the transactions that were added to the pool first
+    Oldest,
+    /// Reject new transactions
+    RejectNew,
+}
+
 pub struct TransactionPool {
     pool: SortedList<u64, Transaction>,
     limit: usize,
+    eviction_policy: EvictionPolicy,
+    next_tx_id: u64,
+    // Lowest gas used by the transactions added so far, a lower bound for the ones in the pool
+    min_gas_used: u64,
 }
 
 impl TransactionPool {
     pub fn new(limit: usize) -> TransactionPool {
+        TransactionPool::with_eviction_policy(limit, EvictionPolicy::LowestPrice)
+    }
+
+    pub fn with_eviction_policy(limit: usize, eviction_policy: EvictionPolicy) -> TransactionPool {
         TransactionPool {
             pool: SortedList::new(),
             limit,
+            eviction_policy,
+            next_tx_id: 0,
+            min_gas_used: u64::MAX,
         }
     }
 
-    pub fn add_txs(&mut self, txs: Vec<Transaction>) {
-        for tx in txs {
-            self.pool.insert(tx.gas_price, tx);
+    /// Adds transactions to the pool, and returns the transactions that were evicted or rejected
+    /// because the pool exceeded its limit. Transactions are numbered in their order of addition.
+    pub fn add_txs(&mut self, txs: Vec<Transaction>) -> Vec<Transaction> {
+        let mut dropped: Vec<Transaction> = Vec::new();
+
+        for mut tx in txs {
+            tx.id = self.next_tx_id;
+            self.next_tx_id += 1;
+            self.min_gas_used = self.min_gas_used.min(tx.gas_used);
+            if self.eviction_policy == EvictionPolicy::RejectNew && self.pool.len() >= self.limit {
+                dropped.push(tx);
+            } else {
+                self.pool.insert(tx.priority(), tx);
+            }
         }
-    }
 
-    pub fn pop_most_valuable_txs(&mut self, total_gas_target: u64) -> Vec<Transaction> {
-        let mut result: Vec<Transaction> = Vec::new();
-        let mut total_gas: u64 = 0;
-        loop {
-            if self.pool.len() == 0 {
-                break;
+        if self.pool.len() <= self.limit {
+            return dropped;
+        }
+
+        let n_excess = self.pool.len() - self.limit;
+
+        match self.eviction_policy {
+            EvictionPolicy::LowestPrice => {
+                dropped.extend(self.pool.drain(0..n_excess).into_iter().map(|(_, tx)| tx));
             }
-            if total_gas + (self.pool.get(self.pool.len() - 1)).gas_used > total_gas_target {
-                break;
+            EvictionPolicy::Oldest => {
+                let mut ids: Vec<u64> = self.pool.values().map(Transaction::id).collect();
+                let (_, &mut min_kept_id, _) = ids.select_nth_unstable(n_excess);
+                self.pool.retain(|_, tx| {
+                    if tx.id < min_kept_id {
+                        dropped.push(*tx);
+                        false
+                    } else {
+                        true
+                    }
+                });
             }
-
-            total_gas += self.pool.get(self.pool.len() - 1).gas_used;
-            result.push(*(self.pool.get(self.pool.len() - 1)));
-            self.pool.remove(self.pool.len() - 1);
+            EvictionPolicy::RejectNew => unreachable!(),
         }
-        result
+
+        dropped
+    }
+
+    /// Removes the transactions selected by the block packer to fill a block of
+    /// `total_gas_target` under `base_fee`, and returns them in their order of inclusion
+    pub fn pop_block_txs(
+        &mut self,
+        packer: &mut BlockPacker,
+        total_gas_target: u64,
+        base_fee: u64,
+    ) -> Vec<Transaction> {
+        let mut n_read = 0;
+        let candidates = self.pool.values().rev().inspect(|_| n_read += 1);
+        let selected: Vec<Transaction> = packer
+            .select(candidates, total_gas_target, base_fee, self.min_gas_used)
+            .into_iter()
+            .copied()
+            .collect();
+
+        // Only the candidates read by the packer, at the top of the pool, can be selected
+        let start = self.pool.len() - n_read;
+        let selected_ids: HashSet<u64> = selected.iter().map(Transaction::id).collect();
+        self.pool
+            .retain_from(start, |_, tx| !selected_ids.contains(&tx.id));
+
+        selected
+    }
+
+    /// Removes the transactions that are expired at the given height and time, and returns them
+    pub fn remove_expired_txs(&mut self, height: u64, time: u64) -> Vec<Transaction> {
+        let mut expired: Vec<Transaction> = Vec::new();
+        self.pool.retain(|_, tx| {
+            if tx.is_expired(height, time) {
+                expired.push(*tx);
+                false
+            } else {
+                true
+            }
+        });
+        expired
     }
 
     pub fn size(&self) -> u64 {
         self.pool.len() as u64
     }
+
+    /// Iterates over the transactions in the pool, from the lowest to the highest priority
+    pub fn txs(&self) -> impl Iterator<Item = &Transaction> {
+        self.pool.values()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EvictionPolicy, Transaction, TransactionPool, Ttl};
+    use crate::packing::BlockPacker;
+
+    #[test]
+    fn test_priority_fee() {
+        let tx = Transaction::new_eip1559(21_000, 100, 10);
+        assert_eq!(tx.priority_fee(50), 10);
+        assert_eq!(tx.priority_fee(95), 5);
+        assert_eq!(tx.effective_gas_price(95), 100);
+        assert!(!tx.is_includable(101));
+
+        let legacy = Transaction::new(21_000, 100);
+        assert_eq!(legacy.priority_fee(0), 100);
+        assert_eq!(legacy.effective_gas_price(0), 100);
+    }
+
+    #[test]
+    fn test_pop_skips_unincludable() {
+        let mut pool = TransactionPool::new(10);
+        pool.add_txs(vec![
+            Transaction::new_eip1559(10, 50, 30),
+            Transaction::new_eip1559(10, 200, 20),
+            Transaction::new_eip1559(10, 200, 10),
+        ]);
+
+        let txs = pool.pop_block_txs(&mut BlockPacker::default(), 100, 100);
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].priority(), 20);
+        assert_eq!(txs[1].priority(), 10);
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn test_evict_lowest_price() {
+        let mut pool = TransactionPool::with_eviction_policy(2, EvictionPolicy::LowestPrice);
+        pool.add_txs(vec![Transaction::new(10, 20), Transaction::new(10, 30)]);
+
+        let dropped = pool.add_txs(vec![Transaction::new(10, 10), Transaction::new(10, 40)]);
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(dropped[0].gas_price(), 10);
+        assert_eq!(dropped[1].gas_price(), 20);
+        assert_eq!(pool.size(), 2);
+    }
+
+    #[test]
+    fn test_evict_oldest() {
+        let mut pool = TransactionPool::with_eviction_policy(2, EvictionPolicy::Oldest);
+        pool.add_txs(vec![Transaction::new(10, 30), Transaction::new(10, 20)]);
+
+        let dropped = pool.add_txs(vec![Transaction::new(10, 10)]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].id(), 0);
+        assert_eq!(dropped[0].gas_price(), 30);
+        assert_eq!(pool.size(), 2);
+    }
+
+    #[test]
+    fn test_reject_new() {
+        let mut pool = TransactionPool::with_eviction_policy(2, EvictionPolicy::RejectNew);
+        pool.add_txs(vec![Transaction::new(10, 20), Transaction::new(10, 30)]);
+
+        let dropped = pool.add_txs(vec![Transaction::new(10, 40)]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].id(), 2);
+        assert_eq!(dropped[0].gas_price(), 40);
+        assert_eq!(pool.size(), 2);
+    }
+
+    #[test]
+    fn test_remove_expired_txs() {
+        let mut pool = TransactionPool::new(10);
+        let in_blocks = Transaction::new(10, 10).with_expiry(Ttl::Blocks(2).expiry(5, 3000));
+        let in_seconds = Transaction::new(10, 10).with_expiry(Ttl::Seconds(600).expiry(5, 3000));
+        pool.add_txs(vec![in_blocks, in_seconds, Transaction::new(10, 10)]);
+
+        let ids = |txs: Vec<Transaction>| txs.iter().map(Transaction::id).collect::<Vec<u64>>();
+        assert!(pool.remove_expired_txs(7, 3600).is_empty());
+        assert_eq!(ids(pool.remove_expired_txs(8, 3600)), vec![0]);
+        assert_eq!(ids(pool.remove_expired_txs(8, 3601)), vec![1]);
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn test_submission_and_inclusion() {
+        let mut tx = Transaction::new(10, 100).submitted(150.5, 3);
+        assert_eq!(tx.inclusion_delay(), None);
+        assert_eq!(tx.surplus(), None);
+
+        tx.include(5, 90);
+        assert_eq!(tx.included_height(), Some(5));
+        assert_eq!(tx.inclusion_delay(), Some(2));
+        assert_eq!(tx.surplus(), Some(605.));
+    }
 }
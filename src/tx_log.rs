@@ -0,0 +1,179 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use crate::transaction::Transaction;
+
+/// Format of the per-transaction output log
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxLogFormat {
+    /// Plain CSV, written to `txs.csv`
+    Csv,
+    /// Gzip compressed CSV, written to `txs.csv.gz`
+    Gzip,
+}
+
+impl TxLogFormat {
+    fn file_name(&self) -> &'static str {
+        match self {
+            TxLogFormat::Csv => "txs.csv",
+            TxLogFormat::Gzip => "txs.csv.gz",
+        }
+    }
+}
+
+/// What eventually happened to a transaction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TxOutcome {
+    /// Included in a block
+    Included,
+    /// Dropped from the transaction pool after its time-to-live
+    Expired,
+    /// Evicted from or rejected by the full transaction pool
+    Evicted,
+    /// Still in the transaction pool at the end of the simulation
+    Pending,
+}
+
+impl TxOutcome {
+    fn name(&self) -> &'static str {
+        match self {
+            TxOutcome::Included => "included",
+            TxOutcome::Expired => "expired",
+            TxOutcome::Evicted => "evicted",
+            TxOutcome::Pending => "pending",
+        }
+    }
+}
+
+/// Destination of the log, kept concrete so that the gzip stream can be finished
+enum TxLogWriter {
+    Csv(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl TxLogWriter {
+    fn as_write(&mut self) -> &mut dyn Write {
+        match self {
+            TxLogWriter::Csv(writer) => writer,
+            TxLogWriter::Gzip(writer) => writer,
+        }
+    }
+}
+
+/// Writes one row per transaction, once its outcome is known
+pub struct TxLog {
+    writer: TxLogWriter,
+    class_labels: Vec<String>,
+}
+
+impl TxLog {
+    /// Creates the log in `output_dir`, naming the classes of users by their labels
+    pub fn create(
+        output_dir: &Path,
+        format: TxLogFormat,
+        class_labels: Vec<String>,
+    ) -> io::Result<TxLog> {
+        let file = BufWriter::new(File::create(output_dir.join(format.file_name()))?);
+
+        let mut writer = match format {
+            TxLogFormat::Csv => TxLogWriter::Csv(file),
+            TxLogFormat::Gzip => TxLogWriter::Gzip(GzEncoder::new(file, Compression::default())),
+        };
+
+        writer.as_write().write_all(
+            "id,class,gas_used,wtp,price_paid,submitted_height,final_height,outcome\n".as_bytes(),
+        )?;
+
+        Ok(TxLog {
+            writer,
+            class_labels,
+        })
+    }
+
+    /// Logs a transaction whose outcome was decided at `height`, or is still pending
+    pub fn write_tx(
+        &mut self,
+        tx: &Transaction,
+        outcome: TxOutcome,
+        height: Option<u64>,
+    ) -> io::Result<()> {
+        writeln!(
+            self.writer.as_write(),
+            "{},{},{},{},{},{},{},{}",
+            tx.id(),
+            self.class_labels[tx.class()],
+            tx.gas_used(),
+            tx.wtp(),
+            tx.price_paid().map(|x| x.to_string()).unwrap_or_default(),
+            tx.submitted_height(),
+            height.map(|x| x.to_string()).unwrap_or_default(),
+            outcome.name()
+        )
+    }
+
+    pub fn write_txs(
+        &mut self,
+        txs: &[Transaction],
+        outcome: TxOutcome,
+        height: Option<u64>,
+    ) -> io::Result<()> {
+        for tx in txs {
+            self.write_tx(tx, outcome, height)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the end of the gzip stream if any, and flushes the log to its file
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self.writer {
+            TxLogWriter::Csv(file) => file,
+            TxLogWriter::Gzip(writer) => writer.finish()?,
+        };
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TxLog, TxLogFormat, TxOutcome};
+    use crate::transaction::Transaction;
+    use crate::transaction::TransactionPool;
+    use flate2::read::GzDecoder;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+
+    #[test]
+    fn test_gzip_log() {
+        let output_dir = std::env::temp_dir().join(format!("tx_log_{}", std::process::id()));
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let mut pool = TransactionPool::new(10);
+        pool.add_txs(vec![
+            Transaction::new(21_000, 10),
+            Transaction::new(50_000, 20),
+        ]);
+        let txs: Vec<Transaction> = pool.txs().copied().collect();
+
+        let mut log =
+            TxLog::create(&output_dir, TxLogFormat::Gzip, vec!["default".into()]).unwrap();
+        log.write_txs(&txs, TxOutcome::Pending, None).unwrap();
+        log.finish().unwrap();
+
+        let mut content = String::new();
+        GzDecoder::new(File::open(output_dir.join("txs.csv.gz")).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "0,default,21000,10,,0,,pending");
+        assert_eq!(lines[2], "1,default,50000,20,,0,,pending");
+    }
+}
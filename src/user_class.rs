@@ -0,0 +1,71 @@
+use crate::demand::KeyframedDemandCurve;
+use crate::gas::GasUsage;
+use crate::transaction::Ttl;
+
+/// Users sharing a demand curve, a distribution of gas usage, a time-to-live and a patience, such
+/// as retail transfers, DeFi traders or bots. Their number in each block is given by a demand profile per
+/// class when running the simulation.
+pub struct UserClass {
+    label: String,
+    demand_curve: KeyframedDemandCurve,
+    tx_gas: GasUsage,
+    tx_ttl: Option<Ttl>,
+    patience: Option<u64>,
+}
+
+impl UserClass {
+    pub fn new(
+        label: String,
+        demand_curve: impl Into<KeyframedDemandCurve>,
+        tx_gas: GasUsage,
+    ) -> UserClass {
+        UserClass {
+            label,
+            demand_curve: demand_curve.into(),
+            tx_gas,
+            tx_ttl: None,
+            patience: None,
+        }
+    }
+
+    /// Sets the distribution of the gas used by the transactions of the class
+    pub fn with_tx_gas(mut self, tx_gas: GasUsage) -> UserClass {
+        self.tx_gas = tx_gas;
+        self
+    }
+
+    /// Sets the time-to-live of the transactions of the class, after which they are dropped from
+    /// the pool
+    pub fn with_tx_ttl(mut self, tx_ttl: Ttl) -> UserClass {
+        self.tx_ttl = Some(tx_ttl);
+        self
+    }
+
+    /// Makes priced-out users wait up to `patience` blocks for the price to drop to their
+    /// willingness to pay, and resubmit then. Otherwise, priced-out users leave right away.
+    pub fn with_patience(mut self, patience: u64) -> UserClass {
+        self.patience = Some(patience);
+        self
+    }
+
+    /// Name of the class in the outputs
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn demand_curve(&self) -> &KeyframedDemandCurve {
+        &self.demand_curve
+    }
+
+    pub fn tx_gas(&self) -> &GasUsage {
+        &self.tx_gas
+    }
+
+    pub fn tx_ttl(&self) -> Option<Ttl> {
+        self.tx_ttl
+    }
+
+    pub fn patience(&self) -> Option<u64> {
+        self.patience
+    }
+}